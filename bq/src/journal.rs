@@ -0,0 +1,145 @@
+use std::fs::File;
+
+use clap::ValueEnum;
+use engine::journal::{self, Entry};
+use polars::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub(crate) enum Format {
+    Csv,
+    Parquet,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+/// 枚举按 serde 的命名输出，与日志文件中的写法一致
+fn name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(s)) => s,
+        _ => String::new(),
+    }
+}
+
+/// 交易日志转为数据表，每条记录一行，触发的策略以逗号拼接
+fn to_frame(entries: &[Entry]) -> PolarsResult<DataFrame> {
+    macro_rules! column {
+        ($name:literal, $f:expr) => {
+            Series::new($name, entries.iter().map($f).collect::<Vec<_>>())
+        };
+    }
+    DataFrame::new(vec![
+        column!("ts", |e| e.ts),
+        column!("kind", |e| name(&e.kind)),
+        column!("inst_id", |e| e.inst_id.clone()),
+        column!("symbol", |e| e.symbol.clone()),
+        column!("strategies", |e| e.strategies.join(",")),
+        column!("side", |e| name(&e.side)),
+        column!("order_id", |e| e.order_id),
+        column!("status", |e| e.status.clone()),
+        column!("price", |e| e.price),
+        column!("quantity", |e| e.quantity),
+        column!("fee", |e| e.fee),
+        column!("fee_asset", |e| e.fee_asset.clone()),
+        column!("pnl", |e| e.pnl),
+    ])
+}
+
+/// 导出交易日志，未指定输出文件时写到日志旁同名的 csv/parquet 文件
+pub(crate) fn export(path: String, format: Format, output: Option<String>) {
+    let entries = match journal::read(&path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Read journal {} failed, {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let output = output.unwrap_or_else(|| {
+        std::path::Path::new(&path)
+            .with_extension(format.extension())
+            .to_string_lossy()
+            .to_string()
+    });
+
+    let result = to_frame(&entries).and_then(|mut df| {
+        let file = File::create(&output)?;
+        match format {
+            Format::Csv => CsvWriter::new(file).finish(&mut df),
+            Format::Parquet => ParquetWriter::new(file).finish(&mut df).map(|_| ()),
+        }
+    });
+    match result {
+        Ok(()) => println!("Exported {} entries to {}", entries.len(), output),
+        Err(e) => {
+            eprintln!("Export journal to {} failed, {}", output, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write};
+
+    use engine::journal::Entry;
+    use polars::prelude::*;
+    use serde_json::json;
+
+    use super::{export, Format};
+
+    #[test]
+    fn test_export() {
+        let path = std::env::temp_dir().join(format!("bq-journal-{}.jsonl", uuid::Uuid::new_v4()));
+        let entries = [
+            json!({
+                "ts": 1, "kind": "fill", "inst_id": "inst-1", "symbol": "BTCUSDT",
+                "strategies": ["rsi-1", "atr-1"], "side": "BUY", "order_id": 1,
+                "status": "Success", "price": 10.0, "quantity": 1.0,
+                "fee": 0.001, "fee_asset": "BTC", "pnl": null,
+            }),
+            json!({
+                "ts": 2, "kind": "order", "inst_id": "inst-1", "symbol": "BTCUSDT",
+                "strategies": [], "side": "SELL", "order_id": 2,
+                "status": "Success", "price": 11.0, "quantity": 1.0,
+                "fee": 0.011, "fee_asset": "USDT", "pnl": 0.5,
+            }),
+        ];
+        {
+            let mut file = File::create(&path).unwrap();
+            for entry in entries {
+                let entry: Entry = serde_json::from_value(entry).unwrap();
+                writeln!(file, "{}", serde_json::to_string(&entry).unwrap()).unwrap();
+            }
+        }
+
+        export(path.to_string_lossy().to_string(), Format::Csv, None);
+        let output = path.with_extension("csv");
+        let df = CsvReader::from_path(&output)
+            .unwrap()
+            .has_header(true)
+            .finish()
+            .unwrap();
+        assert_eq!(df.shape(), (2, 13));
+        let column = |name: &str| df.column(name).unwrap().clone();
+        assert_eq!(column("kind").utf8().unwrap().get(0), Some("fill"));
+        assert_eq!(column("side").utf8().unwrap().get(1), Some("SELL"));
+        assert_eq!(
+            column("strategies").utf8().unwrap().get(0),
+            Some("rsi-1,atr-1")
+        );
+        assert_eq!(column("order_id").i64().unwrap().get(1), Some(2));
+        assert_eq!(column("pnl").f64().unwrap().get(0), None);
+        assert_eq!(column("pnl").f64().unwrap().get(1), Some(0.5));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+}
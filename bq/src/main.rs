@@ -1,9 +1,18 @@
-use clap::{Parser, Subcommand};
-use engine::config::{Config, Strategy};
+mod journal;
+
+use std::collections::HashSet;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use engine::{
+    config::{Config, Strategy},
+    control::{self, Command},
+};
+use strategies::backtest::{self, Backtest};
 use tokio::{
-    fs,
     fs::File,
     io::{AsyncReadExt, AsyncWriteExt},
+    signal::unix::{signal, SignalKind},
+    sync::mpsc::UnboundedSender,
 };
 
 /// Initializes the tracing system for the application.
@@ -35,31 +44,127 @@ enum Commands {
     Run {
         #[arg(short, long, default_value = "./config.toml", value_name = "FILE")]
         config: String,
+        #[arg(short, long, value_enum, default_value_t = RunMode::Live)]
+        mode: RunMode,
+    },
+    #[command(about = "Backtest the config with local kline files.")]
+    Backtest {
+        #[arg(short, long, default_value = "./config.toml", value_name = "FILE")]
+        config: String,
+        /// Directory of `{SYMBOL}-{interval}*.csv` kline files.
+        #[arg(short, long, default_value = "./data", value_name = "DIR")]
+        data: String,
+        #[arg(long, default_value_t = 0.001)]
+        fee_rate: f64,
     },
     #[command(about = "Inject id for config file.")]
     Inject {
         #[arg(short, long, default_value = "./config.toml", value_name = "FILE")]
         config: String,
     },
+    #[command(about = "Control a running engine.")]
+    Ctl {
+        /// Control socket of the engine, `control_path` in the config.
+        #[arg(short, long, default_value = "./bq.sock", value_name = "FILE")]
+        socket: String,
+        #[command(subcommand)]
+        command: CtlCommand,
+    },
+    #[command(about = "Work with the trade journal.")]
+    Journal {
+        #[command(subcommand)]
+        command: JournalCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum JournalCommand {
+    /// Export the journal for analysis.
+    Export {
+        /// Trade journal of the engine, `journal_path` in the config.
+        #[arg(short, long, default_value = "./bq-journal.jsonl", value_name = "FILE")]
+        journal: String,
+        #[arg(short, long, value_enum, default_value_t = journal::Format::Csv)]
+        format: journal::Format,
+        /// Defaults to the journal path with the extension of the format.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CtlCommand {
+    /// Show principal, profit and instance states.
+    Status,
+    /// List open and archived orders.
+    Orders,
+    /// Stop acting on trade decisions of an instance.
+    Pause { inst_id: String },
+    /// Resume a paused instance.
+    Resume { inst_id: String },
+    /// Cancel the open order of an instance and sell its position at market.
+    Close { inst_id: String },
+    /// Lift a risk halt, which otherwise survives restarts.
+    ResumeTrading,
+}
+
+impl From<CtlCommand> for Command {
+    fn from(command: CtlCommand) -> Self {
+        match command {
+            CtlCommand::Status => Command::Status,
+            CtlCommand::Orders => Command::Orders,
+            CtlCommand::Pause { inst_id } => Command::Pause { inst_id },
+            CtlCommand::Resume { inst_id } => Command::Resume { inst_id },
+            CtlCommand::Close { inst_id } => Command::Close { inst_id },
+            CtlCommand::ResumeTrading => Command::ResumeTrading,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum RunMode {
+    /// Trade on the exchange with real funds.
+    Live,
+    /// Match orders in-process against live market data.
+    Paper,
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Client::parse();
     match cli.command {
-        Commands::Run { config } => {
-            run_engine(config).await;
+        Commands::Run { config, mode } => {
+            run_engine(config, mode).await;
+        }
+        Commands::Backtest {
+            config,
+            data,
+            fee_rate,
+        } => {
+            run_backtest(config, data, fee_rate).await;
         }
         Commands::Inject { config } => {
             inject_id_with_config(config).await;
         }
+        Commands::Ctl { socket, command } => {
+            run_ctl(socket, command.into()).await;
+        }
+        Commands::Journal { command } => match command {
+            JournalCommand::Export {
+                journal,
+                format,
+                output,
+            } => {
+                journal::export(journal, format, output);
+            }
+        },
     }
 }
 
-async fn run_engine(config: String) {
+async fn run_engine(config: String, mode: RunMode) {
     init_tracing();
 
-    let mut file = File::open(config).await.expect("config.toml not exist");
+    let mut file = File::open(&config).await.expect("config.toml not exist");
     let mut str = String::new();
     file.read_to_string(&mut str)
         .await
@@ -67,9 +172,115 @@ async fn run_engine(config: String) {
 
     let conf: Config = toml::from_str(&str).expect("msg");
 
-    let mut e = engine::Engine::new_with_env(conf);
+    let mut e = match mode {
+        RunMode::Live => engine::Engine::new_with_env(conf),
+        RunMode::Paper => engine::Engine::new_paper(conf),
+    };
+    tokio::spawn(reload_on_hangup(config, e.reloader()));
     tracing::info!("Engine started");
-    e.run().await;
+    let summary = e.run(shutdown_signal()).await;
+    println!("{}", summary);
+}
+
+/// Waits for SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("install SIGTERM handler failed");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+/// Re-reads the config file on every SIGHUP and hands it to the engine.
+async fn reload_on_hangup(path: String, reload_tx: UnboundedSender<Config>) {
+    let mut hangup = signal(SignalKind::hangup()).expect("install SIGHUP handler failed");
+    while hangup.recv().await.is_some() {
+        tracing::info!("Received SIGHUP, reload {}", path);
+        let conf = match tokio::fs::read_to_string(&path).await {
+            Ok(str) => toml::from_str::<Config>(&str).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match conf {
+            Ok(conf) => {
+                if reload_tx.send(conf).is_err() {
+                    break;
+                }
+            }
+            Err(e) => tracing::error!("Reload config failed, {}", e),
+        }
+    }
+}
+
+async fn run_ctl(socket: String, command: Command) {
+    match control::request(&socket, &command).await {
+        Ok(Ok(value)) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&value).expect("format reply failed")
+            );
+        }
+        Ok(Err(e)) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Connect to engine at {} failed, {}", socket, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_backtest(config: String, data: String, fee_rate: f64) {
+    let mut file = File::open(config).await.expect("config.toml not exist");
+    let mut str = String::new();
+    file.read_to_string(&mut str)
+        .await
+        .expect("read config.toml failed");
+    let conf: Config = toml::from_str(&str).expect("msg");
+
+    let mut total = 0.;
+    for instance in conf.instances.iter() {
+        let symbol = instance.symbol.to_uppercase();
+
+        // 合并各周期的K线，按收盘时间回放
+        let intervals = instance
+            .strategies
+            .iter()
+            .map(Strategy::interval)
+            .collect::<HashSet<_>>();
+        let mut klines = Vec::new();
+        for interval in intervals {
+            let mut loaded =
+                backtest::load_dir(&data, &symbol, interval).expect("read kline files failed");
+            if loaded.is_empty() {
+                println!("No kline files for {}-{} in {}", symbol, interval, data);
+            }
+            klines.append(&mut loaded);
+        }
+        klines.sort_by_key(|k| k.kline.end_time);
+
+        let strategies = instance.strategies.iter().map(Strategy::build).collect();
+        let weights = instance
+            .strategies
+            .iter()
+            .map(Strategy::weight)
+            .collect::<Vec<_>>();
+        let mut backtest = Backtest::new(strategies, instance.principal, fee_rate);
+        let report = backtest.run(&klines, |signals| {
+            instance.mode.decide(signals, &weights, instance.threshold)
+        });
+        println!(
+            "{} {}: klines {}, trades {}, equity {:.4}, profit {:.2}%",
+            instance.id,
+            symbol,
+            klines.len(),
+            report.trades,
+            report.equity,
+            report.profit_rate() * 100.
+        );
+        total += report.equity;
+    }
+    println!("Final equity: {:.4}", total);
 }
 
 async fn inject_id_with_config(config: String) {
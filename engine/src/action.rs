@@ -1,32 +1,36 @@
 use async_trait::async_trait;
 use binance::{
-    account::{Account, OrderCancellation, OrderRequest},
-    rest_model::{OrderSide, OrderType},
+    account::{OrderCancellation, OrderRequest},
+    errors::Result,
+    rest_model::{OrderCanceled, OrderSide, OrderType, TimeInForce, Transaction},
 };
 
+use crate::exchange::{Exchange, Exchanges};
+
 pub(crate) struct ExpectBuy {
     pub(crate) symbol: String,
     pub(crate) price: f64,
     pub(crate) quantity: f64,
+    pub(crate) client_order_id: String,
 }
 
 impl ExpectBuy {
-    async fn create_limit_buy(&self, account: &Account) {
+    async fn create_limit_buy(&self, exchange: &Exchanges) -> Result<Transaction> {
         let order = OrderRequest {
             symbol: self.symbol.clone(),
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
-            time_in_force: None,
-            quantity: None,
+            time_in_force: Some(TimeInForce::GTC),
+            quantity: Some(self.quantity),
             quote_order_qty: None,
             price: Some(self.price),
-            new_client_order_id: None,
+            new_client_order_id: Some(self.client_order_id.clone()),
             stop_price: None,
             iceberg_qty: None,
             new_order_resp_type: None,
             recv_window: None,
         };
-        let _resp = account.place_order(order).await;
+        exchange.place_order(order).await
     }
 }
 
@@ -34,60 +38,94 @@ pub(crate) struct ExpectSell {
     pub(crate) symbol: String,
     pub(crate) price: f64,
     pub(crate) quantity: f64,
+    pub(crate) client_order_id: String,
 }
 
 #[async_trait]
 pub trait Handle {
-    async fn handle(&self, account: &Account);
+    async fn handle(&self, exchange: &Exchanges) -> Result<Transaction>;
+
+    /// 订单方向，用于风控检查
+    fn side(&self) -> OrderSide;
 }
 
 #[async_trait]
 impl Handle for ExpectBuy {
-    async fn handle(&self, account: &Account) {
-        self.create_limit_buy(account).await;
+    async fn handle(&self, exchange: &Exchanges) -> Result<Transaction> {
+        self.create_limit_buy(exchange).await
+    }
+
+    fn side(&self) -> OrderSide {
+        OrderSide::Buy
     }
 }
 
 #[async_trait]
 impl Handle for ExpectSell {
-    async fn handle(&self, account: &Account) {
+    async fn handle(&self, exchange: &Exchanges) -> Result<Transaction> {
         let order = OrderRequest {
             symbol: self.symbol.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
-            time_in_force: None,
+            time_in_force: Some(TimeInForce::GTC),
             quantity: Some(self.quantity),
             quote_order_qty: None,
             price: Some(self.price),
-            new_client_order_id: None,
+            new_client_order_id: Some(self.client_order_id.clone()),
             stop_price: None,
             iceberg_qty: None,
             new_order_resp_type: None,
             recv_window: None,
         };
-        let _resp = account.place_order(order).await;
+        exchange.place_order(order).await
+    }
+
+    fn side(&self) -> OrderSide {
+        OrderSide::Sell
+    }
+}
+
+/// 市价卖出，用于止损离场。
+pub(crate) struct MarketSell {
+    pub(crate) symbol: String,
+    pub(crate) quantity: f64,
+    pub(crate) client_order_id: String,
+}
+
+#[async_trait]
+impl Handle for MarketSell {
+    async fn handle(&self, exchange: &Exchanges) -> Result<Transaction> {
+        let order = OrderRequest {
+            symbol: self.symbol.clone(),
+            side: OrderSide::Sell,
+            order_type: OrderType::Market,
+            quantity: Some(self.quantity),
+            new_client_order_id: Some(self.client_order_id.clone()),
+            ..Default::default()
+        };
+        exchange.place_order(order).await
+    }
+
+    fn side(&self) -> OrderSide {
+        OrderSide::Sell
     }
 }
 
 pub(crate) struct RevokeOrder {
     pub(crate) symbol: String,
-    pub(crate) order_id: String,
+    pub(crate) order_id: u64,
 }
 
 impl RevokeOrder {
-    async fn revoke_order(&self, account: &Account) {
-        let resp = account
+    pub(crate) async fn revoke_order(&self, exchange: &Exchanges) -> Result<OrderCanceled> {
+        exchange
             .cancel_order(OrderCancellation {
                 symbol: self.symbol.clone(),
-                order_id: None,
+                order_id: Some(self.order_id),
                 orig_client_order_id: None,
                 new_client_order_id: None,
                 recv_window: None,
             })
-            .await;
-        match resp {
-            Ok(resp) => {}
-            Err(e) => {}
-        }
+            .await
     }
 }
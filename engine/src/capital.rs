@@ -0,0 +1,214 @@
+use std::{collections::HashMap, fmt};
+
+use crate::InstId;
+
+/// 资金分配失败的原因
+#[derive(Debug, PartialEq)]
+pub enum CapitalError {
+    /// 各实例本金之和超过全局本金
+    OverAllocated { allocated: f64, principal: f64 },
+    /// 实例预算不足
+    InsufficientBudget {
+        inst_id: InstId,
+        required: f64,
+        available: f64,
+    },
+    /// 实例未分配预算
+    UnknownInstance(InstId),
+}
+
+impl fmt::Display for CapitalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapitalError::OverAllocated {
+                allocated,
+                principal,
+            } => write!(
+                f,
+                "instance principals {} exceed global principal {}",
+                allocated, principal
+            ),
+            CapitalError::InsufficientBudget {
+                inst_id,
+                required,
+                available,
+            } => write!(
+                f,
+                "instance {} requires {} but only {} is available",
+                inst_id, required, available
+            ),
+            CapitalError::UnknownInstance(inst_id) => {
+                write!(f, "instance {} has no budget", inst_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CapitalError {}
+
+/// 单个实例的预算
+#[derive(Debug, Default)]
+struct Budget {
+    principal: f64,
+    used: f64, // 挂单与持仓占用的资金
+}
+
+/// 资金分配：从全局本金中为每个实例预留本金，下单前占用，成交或撤单后释放。
+#[derive(Debug)]
+pub(crate) struct Capital {
+    principal: f64,
+    budgets: HashMap<InstId, Budget>,
+}
+
+impl Capital {
+    pub(crate) fn new(principal: f64) -> Self {
+        Self {
+            principal,
+            budgets: HashMap::new(),
+        }
+    }
+
+    /// 为实例预留本金，所有实例的本金之和不能超过全局本金。
+    pub(crate) fn reserve(&mut self, inst_id: &str, principal: f64) -> Result<(), CapitalError> {
+        let allocated = self.allocated() + principal;
+        if allocated > self.principal {
+            return Err(CapitalError::OverAllocated {
+                allocated,
+                principal: self.principal,
+            });
+        }
+        self.budgets
+            .entry(inst_id.to_string())
+            .or_default()
+            .principal += principal;
+        Ok(())
+    }
+
+    /// 重新分配本金：校验通过后替换全局本金与各实例本金，移除不再存在的实例。
+    pub(crate) fn reallocate(
+        &mut self,
+        principal: f64,
+        budgets: &HashMap<InstId, f64>,
+    ) -> Result<(), CapitalError> {
+        let allocated = budgets.values().sum::<f64>();
+        if allocated > principal {
+            return Err(CapitalError::OverAllocated {
+                allocated,
+                principal,
+            });
+        }
+        self.principal = principal;
+        self.budgets
+            .retain(|inst_id, _| budgets.contains_key(inst_id));
+        for (inst_id, principal) in budgets {
+            self.budgets.entry(inst_id.clone()).or_default().principal = *principal;
+        }
+        Ok(())
+    }
+
+    /// 已预留给实例的本金
+    pub(crate) fn allocated(&self) -> f64 {
+        self.budgets.values().map(|b| b.principal).sum()
+    }
+
+    /// 实例挂单与持仓占用的资金
+    pub(crate) fn used(&self, inst_id: &str) -> f64 {
+        self.budgets
+            .get(inst_id)
+            .map(|b| b.used)
+            .unwrap_or_default()
+    }
+
+    /// 所有实例占用的资金
+    pub(crate) fn total_used(&self) -> f64 {
+        self.budgets.values().map(|b| b.used).sum()
+    }
+
+    /// 实例当前可用的资金
+    pub(crate) fn available(&self, inst_id: &str) -> f64 {
+        self.budgets
+            .get(inst_id)
+            .map(|b| (b.principal - b.used).max(0.))
+            .unwrap_or_default()
+    }
+
+    /// 下单前占用资金，超出实例预算时拒绝。
+    pub(crate) fn lock(&mut self, inst_id: &str, amount: f64) -> Result<(), CapitalError> {
+        let budget = self
+            .budgets
+            .get_mut(inst_id)
+            .ok_or_else(|| CapitalError::UnknownInstance(inst_id.to_string()))?;
+        let available = budget.principal - budget.used;
+        if amount > available {
+            return Err(CapitalError::InsufficientBudget {
+                inst_id: inst_id.to_string(),
+                required: amount,
+                available,
+            });
+        }
+        budget.used += amount;
+        Ok(())
+    }
+
+    /// 买单撤销或卖单成交后释放资金
+    pub(crate) fn release(&mut self, inst_id: &str, amount: f64) {
+        if let Some(budget) = self.budgets.get_mut(inst_id) {
+            budget.used = (budget.used - amount).max(0.);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Capital, CapitalError};
+
+    #[test]
+    fn test_capital() {
+        let mut capital = Capital::new(100.);
+        capital.reserve("a", 60.).unwrap();
+        assert!(matches!(
+            capital.reserve("b", 50.),
+            Err(CapitalError::OverAllocated { .. })
+        ));
+        capital.reserve("b", 40.).unwrap();
+
+        capital.lock("a", 50.).unwrap();
+        assert_eq!(capital.available("a"), 10.);
+        assert!(matches!(
+            capital.lock("a", 20.),
+            Err(CapitalError::InsufficientBudget { .. })
+        ));
+
+        capital.release("a", 50.);
+        assert_eq!(capital.available("a"), 60.);
+        assert_eq!(
+            capital.lock("c", 1.),
+            Err(CapitalError::UnknownInstance("c".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_reallocate() {
+        let mut capital = Capital::new(100.);
+        capital.reserve("a", 60.).unwrap();
+        capital.reserve("b", 40.).unwrap();
+        capital.lock("a", 30.).unwrap();
+
+        let budgets = HashMap::from([("a".to_string(), 80.), ("c".to_string(), 50.)]);
+        assert!(matches!(
+            capital.reallocate(120., &budgets),
+            Err(CapitalError::OverAllocated { .. })
+        ));
+        assert_eq!(capital.available("a"), 30.);
+
+        capital.reallocate(150., &budgets).unwrap();
+        assert_eq!(capital.available("a"), 50.);
+        assert_eq!(capital.available("c"), 50.);
+        assert_eq!(
+            capital.lock("b", 1.),
+            Err(CapitalError::UnknownInstance("b".to_string()))
+        );
+    }
+}
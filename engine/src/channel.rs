@@ -1,4 +1,4 @@
-use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc};
 
 pub struct Mpsc<Data> {
     pub(crate) tx: mpsc::UnboundedSender<Data>,
@@ -8,10 +8,7 @@ pub struct Mpsc<Data> {
 impl<Data> Default for Mpsc<Data> {
     fn default() -> Self {
         let (tx, rx) = mpsc::unbounded_channel();
-        Self {
-            tx: tx,
-            rx: Some(rx),
-        }
+        Self { tx, rx: Some(rx) }
     }
 }
 
@@ -32,15 +29,3 @@ where
         Self { tx }
     }
 }
-
-pub struct Oneshot<Data> {
-    pub tx: Option<oneshot::Sender<Data>>,
-    pub rx: Option<oneshot::Receiver<Data>>,
-}
-
-impl<Data> Default for Oneshot<Data> {
-    fn default() -> Self {
-        let (tx, rx) = oneshot::channel();
-        Self { tx:Some(tx), rx:Some(rx) }
-    }
-}
@@ -1,17 +1,79 @@
 use serde::{Deserialize, Serialize};
-use strategies::KlineInterval;
+use strategies::{
+    atr::AverageTrueRange, rsi::RelativeStrengthIndex, Evaluation, KlineInterval, Strategies,
+};
 
-use crate::instance::StrategyMode;
+use crate::instance::{StaleAction, StrategyMode};
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     #[serde(rename = "principal")]
     pub principal: f64,
 
+    /// 状态日志文件，重启后从中恢复订单与实例状态
+    #[serde(rename = "state_path", default = "default_state_path")]
+    pub state_path: String,
+
+    /// 控制接口的 Unix socket，`bq ctl` 通过它查询与操作运行中的引擎，为空则不启动
+    #[serde(rename = "control_path", default = "default_control_path")]
+    pub control_path: String,
+
+    /// Prometheus 指标的监听地址，例如 127.0.0.1:9184，为空则不启动
+    #[serde(rename = "metrics_addr", default)]
+    pub metrics_addr: String,
+
+    /// 交易日志文件，记录决策、订单与成交，供 `bq journal export` 导出，为空则不记录
+    #[serde(rename = "journal_path", default = "default_journal_path")]
+    pub journal_path: String,
+
     //    #[serde(rename = "data_stream")]
     //    pub data_stream: DataStream,
     #[serde(rename = "instances")]
     pub instances: Vec<Instance>,
+
+    /// 全局风控，不配置则不限制
+    #[serde(rename = "risk", default)]
+    pub risk: RiskLimits,
+
+    /// 退出时的挂单与持仓处理
+    #[serde(rename = "shutdown", default)]
+    pub shutdown: Shutdown,
+}
+
+/// 退出时的挂单与持仓处理，默认保留
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Shutdown {
+    /// 撤销所有挂单，否则挂单保留在交易所，重启后对账恢复
+    #[serde(rename = "cancel_orders", default)]
+    pub cancel_orders: bool,
+
+    /// 撤单后市价卖出所有持仓
+    #[serde(rename = "flatten", default)]
+    pub flatten: bool,
+}
+
+/// 风控限制，值为 0 表示不限制
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RiskLimits {
+    /// 单个交易对的最大敞口，即买单与持仓占用的资金
+    #[serde(rename = "max_symbol_exposure", default)]
+    pub max_symbol_exposure: f64,
+
+    /// 所有交易对的最大敞口
+    #[serde(rename = "max_total_exposure", default)]
+    pub max_total_exposure: f64,
+
+    /// 当日（UTC）最大已实现亏损，达到后停止交易
+    #[serde(rename = "max_daily_loss", default)]
+    pub max_daily_loss: f64,
+
+    /// 权益自峰值的最大回撤比例，达到后停止交易
+    #[serde(rename = "max_drawdown", default)]
+    pub max_drawdown: f64,
+
+    /// 每分钟最多提交的订单数
+    #[serde(rename = "max_orders_per_minute", default)]
+    pub max_orders_per_minute: u32,
 }
 //
 //#[derive(Serialize, Deserialize)]
@@ -26,7 +88,7 @@ pub struct Config {
 //    pub interval: Vec<Interval>,
 //}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Instance {
     #[serde(default)]
     pub id: String,
@@ -37,6 +99,10 @@ pub struct Instance {
     #[serde(rename = "mode")]
     pub mode: StrategyMode,
 
+    /// 权重模式下同向信号的权重占比阈值
+    #[serde(rename = "threshold", default = "default_threshold")]
+    pub threshold: f64,
+
     #[serde(rename = "strategies")]
     pub strategies: Vec<Strategy>,
 
@@ -45,9 +111,41 @@ pub struct Instance {
 
     #[serde(rename = "principal")]
     pub principal: f64,
+
+    /// 限价单相对最优价的偏移比例，正数更保守，负数更激进
+    #[serde(rename = "price_offset", default)]
+    pub price_offset: f64,
+
+    /// 挂单超时未成交的处理策略
+    #[serde(rename = "stale_order", default)]
+    pub stale_order: StaleOrder,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct StaleOrder {
+    /// 挂单超过该秒数未成交视为过期，0 表示不处理
+    #[serde(rename = "timeout", default)]
+    pub timeout: u64,
+
+    #[serde(rename = "action", default)]
+    pub action: StaleAction,
+
+    /// 追价的最大次数，用完后撤单
+    #[serde(rename = "max_chase", default = "default_max_chase")]
+    pub max_chase: u32,
+}
+
+impl Default for StaleOrder {
+    fn default() -> Self {
+        Self {
+            timeout: 0,
+            action: StaleAction::default(),
+            max_chase: default_max_chase(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Strategy {
     Rsi {
@@ -68,6 +166,12 @@ pub enum Strategy {
 
         #[serde(rename = "sell_threshold")]
         sell_threshold: f64,
+
+        #[serde(rename = "weight", default = "default_weight")]
+        weight: f64,
+
+        #[serde(rename = "evaluation", default)]
+        evaluation: Evaluation,
     },
     Atr {
         #[serde(default)]
@@ -84,10 +188,93 @@ pub enum Strategy {
 
         #[serde(rename = "threshold")]
         threshold: f64,
+
+        #[serde(rename = "weight", default = "default_weight")]
+        weight: f64,
+
+        #[serde(rename = "evaluation", default)]
+        evaluation: Evaluation,
     },
 }
 
-#[derive(Serialize, Deserialize)]
+impl Strategy {
+    /// 策略ID
+    pub fn id(&self) -> &str {
+        match self {
+            Strategy::Rsi { id, .. } | Strategy::Atr { id, .. } => id,
+        }
+    }
+
+    /// 策略使用的K线周期
+    pub fn interval(&self) -> &KlineInterval {
+        match self {
+            Strategy::Rsi { interval, .. } | Strategy::Atr { interval, .. } => interval,
+        }
+    }
+
+    /// 策略在权重模式下的权重
+    pub fn weight(&self) -> f64 {
+        match self {
+            Strategy::Rsi { weight, .. } | Strategy::Atr { weight, .. } => *weight,
+        }
+    }
+
+    /// 根据配置创建策略，实盘与回测共用。
+    pub fn build(&self) -> Strategies {
+        match self {
+            Strategy::Rsi {
+                interval,
+                period,
+                buy_threshold,
+                sell_threshold,
+                evaluation,
+                ..
+            } => Strategies::RelativeStrengthIndex(
+                RelativeStrengthIndex::new(
+                    *period,
+                    interval.clone(),
+                    *buy_threshold,
+                    *sell_threshold,
+                )
+                .with_evaluation(*evaluation),
+            ),
+            Strategy::Atr {
+                interval,
+                period,
+                evaluation,
+                ..
+            } => Strategies::AverageTrueRange(
+                AverageTrueRange::new(*period, interval.clone()).with_evaluation(*evaluation),
+            ),
+        }
+    }
+}
+
+fn default_state_path() -> String {
+    "bq-state.jsonl".to_string()
+}
+
+fn default_journal_path() -> String {
+    "bq-journal.jsonl".to_string()
+}
+
+fn default_control_path() -> String {
+    "bq.sock".to_string()
+}
+
+fn default_threshold() -> f64 {
+    0.5
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_max_chase() -> u32 {
+    3
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum StrategyType {
     RSI,
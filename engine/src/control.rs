@@ -0,0 +1,174 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, Permissions},
+    io,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc::UnboundedSender, oneshot},
+};
+
+use crate::{exchange::RateUsage, instance::State, InstId, OrderId, Pnl, Symbol};
+
+/// 控制命令，客户端每次连接发送一行 JSON，引擎回复一行 JSON 后断开。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// 本金、收益与各实例状态
+    Status,
+    /// 当前订单与已归档订单
+    Orders,
+    /// 暂停实例，不再处理交易决策
+    Pause { inst_id: InstId },
+    /// 恢复实例
+    Resume { inst_id: InstId },
+    /// 撤销挂单并市价卖出持仓
+    Close { inst_id: InstId },
+    /// 解除风控停止交易，重启后停止交易仍会保留，只能以此解除
+    ResumeTrading,
+}
+
+/// 命令的执行结果
+pub type Reply = Result<Value, String>;
+
+/// 发给交易处理的命令及回复通道
+pub(crate) type Control = (Command, oneshot::Sender<Reply>);
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Status {
+    pub(crate) principal: f64,
+    pub(crate) profit: f64,
+    pub(crate) halted: Option<String>, // 风控停止交易的原因
+    pub(crate) instances: Vec<InstanceStatus>,
+    pub(crate) symbols: BTreeMap<Symbol, Pnl>, // 各交易对的盈亏
+    pub(crate) rate_usage: Vec<RateUsage>,     // REST 请求额度的当前用量
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct InstanceStatus {
+    pub(crate) id: InstId,
+    pub(crate) symbol: Symbol,
+    pub(crate) state: State,
+    pub(crate) paused: bool,
+    pub(crate) stopping: bool,
+    pub(crate) order_id: Option<OrderId>,
+    pub(crate) quantity: f64, // 持仓数量
+    pub(crate) buy_price: f64,
+    pub(crate) cost: f64,
+    pub(crate) pnl: Pnl,
+}
+
+/// 在 Unix socket 上监听控制命令，转发给交易处理并回复结果。
+pub(crate) fn serve(path: &str, control_tx: UnboundedSender<Control>) -> io::Result<()> {
+    // 上次运行遗留的 socket 文件会导致绑定失败
+    if Path::new(path).exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    // 控制命令可以平仓与解除风控，只允许运行引擎的用户连接
+    fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    tracing::info!("Control server listening on {}", path);
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::error!("Accept control connection failed, {:?}", e);
+                    continue;
+                }
+            };
+            let control_tx = control_tx.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, control_tx).await {
+                    tracing::error!("Handle control connection failed, {:?}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn handle(stream: UnixStream, control_tx: UnboundedSender<Control>) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    let reply = match serde_json::from_str::<Command>(&line) {
+        Ok(command) => {
+            tracing::info!("Control command, {:?}", command);
+            let (tx, rx) = oneshot::channel();
+            match control_tx.send((command, tx)) {
+                Ok(()) => rx
+                    .await
+                    .unwrap_or_else(|_| Err("Engine is stopping".to_string())),
+                Err(_) => Err("Engine is stopping".to_string()),
+            }
+        }
+        Err(e) => Err(format!("Invalid command, {}", e)),
+    };
+    let mut reply = serde_json::to_string(&reply)?;
+    reply.push('\n');
+    writer.write_all(reply.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+/// 连接运行中的引擎并发送命令
+pub async fn request(path: &str, command: &Command) -> io::Result<Reply> {
+    let stream = UnixStream::connect(path).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut line = serde_json::to_string(command)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use serde_json::json;
+
+    use super::{request, serve, Command, Control};
+    use crate::channel::Mpsc;
+
+    #[tokio::test]
+    async fn test_control() {
+        let path = std::env::temp_dir().join(format!("bq-{}.sock", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let mut control = Mpsc::<Control>::default();
+        serve(path, control.tx.clone()).unwrap();
+        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let mut control_rx = control.rx.take().unwrap();
+        tokio::spawn(async move {
+            while let Some((command, reply_tx)) = control_rx.recv().await {
+                let reply = match command {
+                    Command::Pause { inst_id } => Ok(json!({ "paused": inst_id })),
+                    _ => Err("Unsupported".to_string()),
+                };
+                let _ = reply_tx.send(reply);
+            }
+        });
+
+        let reply = request(
+            path,
+            &Command::Pause {
+                inst_id: "inst-1".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(reply, Ok(json!({ "paused": "inst-1" })));
+        let reply = request(path, &Command::Status).await.unwrap();
+        assert_eq!(reply, Err("Unsupported".to_string()));
+        std::fs::remove_file(path).unwrap();
+    }
+}
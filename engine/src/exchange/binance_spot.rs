@@ -0,0 +1,379 @@
+use std::{
+    future::Future,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use binance::{
+    account::{Account, OrderCancellation, OrderRequest, OrderStatusRequest, OrdersQuery},
+    api::Binance,
+    config::Config,
+    errors::{Error, Result},
+    general::General,
+    market::Market,
+    rest_model::{
+        Balance, ExchangeInformation, KlineSummaries, KlineSummary, Order, OrderCanceled,
+        OrderSide, RateLimitInterval, Transaction,
+    },
+    userstream::UserStream,
+    util::build_signed_request,
+    websockets::{book_ticker_stream, kline_stream},
+    ws_model::{WebsocketEvent, WebsocketEventUntag},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use strategies::{Data, DataIndex, KlineInterval};
+use tokio::{sync::RwLock, time};
+
+use super::{
+    limiter::{interval_ms, weight, Priority, RateLimiter, RateUsage},
+    stream::StreamManager,
+    Channels, Exchange,
+};
+use crate::{filter::normalize_notional, metrics::METRICS};
+
+/// listen key 的续期间隔
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// 币安现货
+pub struct BinanceSpot {
+    account: Account,
+    general: General,
+    market: Market,
+    user_stream: UserStream,
+    limiter: Arc<RateLimiter>, // 所有 REST 请求共享的限流器
+    http: reqwest::Client,     // 读取响应头中的请求权重用量，币安客户端不返回响应头
+}
+
+/// 账户在一个窗口内的下单次数，`GET /api/v3/rateLimit/order` 的一项
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderCount {
+    interval: RateLimitInterval,
+    interval_num: u64,
+    count: u32,
+}
+
+impl BinanceSpot {
+    pub fn new(api_key: &str, secret_key: &str) -> Self {
+        tracing::info!(api_key);
+        Self {
+            account: Account::new(Some(api_key.to_string()), Some(secret_key.to_string())),
+            general: General::new(None, None),
+            market: Market::new(None, None),
+            user_stream: UserStream::new(Some(api_key.to_string()), Some(secret_key.to_string())),
+            limiter: Default::default(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// 占用额度后发出请求，并根据结果判断是否被限流
+    async fn limited<T>(
+        &self,
+        weight: u32,
+        orders: u32,
+        priority: Priority,
+        request: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        limited(&self.limiter, weight, orders, priority, request).await
+    }
+}
+
+/// 获取交易规则，NOTIONAL 过滤器改写后再解析，否则会被忽略
+pub(crate) async fn exchange_info(general: &General) -> Result<ExchangeInformation> {
+    let mut info: Value = general.client.get("/api/v3/exchangeInfo", None).await?;
+    normalize_notional(&mut info);
+    Ok(serde_json::from_value(info)?)
+}
+
+// 后台任务不持有 BinanceSpot，直接使用共享的限流器
+async fn limited<T>(
+    limiter: &RateLimiter,
+    weight: u32,
+    orders: u32,
+    priority: Priority,
+    request: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    limiter.acquire(weight, orders, priority).await?;
+    let result = request.await;
+    limiter.on_result(&result).await;
+    result
+}
+
+#[async_trait]
+impl Exchange for BinanceSpot {
+    async fn place_order(&self, order: OrderRequest) -> Result<Transaction> {
+        // 买单只是错过一次开仓机会，接近限额时优先丢弃；卖单关系到止损与平仓，等待额度
+        let priority = match order.side {
+            OrderSide::Buy => Priority::Low,
+            OrderSide::Sell => Priority::High,
+        };
+        self.limited(
+            weight::PLACE_ORDER,
+            1,
+            priority,
+            self.account.place_order(order),
+        )
+        .await
+    }
+
+    async fn cancel_order(&self, cancellation: OrderCancellation) -> Result<OrderCanceled> {
+        self.limited(
+            weight::CANCEL_ORDER,
+            0,
+            Priority::High,
+            self.account.cancel_order(cancellation),
+        )
+        .await
+    }
+
+    async fn order_status(&self, request: OrderStatusRequest) -> Result<Order> {
+        self.limited(
+            weight::ORDER_STATUS,
+            0,
+            Priority::High,
+            self.account.order_status(request),
+        )
+        .await
+    }
+
+    async fn open_orders(&self, symbol: &str) -> Result<Vec<Order>> {
+        self.limited(
+            weight::OPEN_ORDERS,
+            0,
+            Priority::High,
+            self.account.get_open_orders(symbol),
+        )
+        .await
+    }
+
+    async fn all_orders(&self, symbol: &str) -> Result<Vec<Order>> {
+        let query = OrdersQuery {
+            symbol: symbol.to_string(),
+            ..Default::default()
+        };
+        self.limited(
+            weight::ALL_ORDERS,
+            0,
+            Priority::High,
+            self.account.get_all_orders(query),
+        )
+        .await
+    }
+
+    async fn balance(&self, asset: &str) -> Result<Balance> {
+        self.limited(
+            weight::ACCOUNT,
+            0,
+            Priority::High,
+            self.account.get_balance(asset),
+        )
+        .await
+    }
+
+    async fn exchange_info(&self) -> Result<ExchangeInformation> {
+        let info = self
+            .limited(
+                weight::EXCHANGE_INFO,
+                0,
+                Priority::High,
+                exchange_info(&self.general),
+            )
+            .await?;
+        self.limiter.set_limits(&info.rate_limits).await;
+        Ok(info)
+    }
+
+    async fn klines(
+        &self,
+        symbol: &str,
+        interval: &KlineInterval,
+        limit: u16,
+    ) -> Result<Vec<KlineSummary>> {
+        let KlineSummaries::AllKlineSummaries(klines) = self
+            .limited(
+                weight::KLINES,
+                0,
+                Priority::High,
+                self.market
+                    .get_klines(symbol, interval.to_string(), limit, None, None),
+            )
+            .await?;
+        Ok(klines)
+    }
+
+    async fn price(&self, symbol: &str) -> Result<f64> {
+        let price = self
+            .limited(
+                weight::TICKER_PRICE,
+                0,
+                Priority::High,
+                self.market.get_price(symbol),
+            )
+            .await?;
+        Ok(price.price)
+    }
+
+    async fn rate_usage(&self) -> Vec<RateUsage> {
+        self.limiter.usage().await
+    }
+
+    async fn sync_rate_usage(&self) -> Result<()> {
+        // IP 的请求权重只在响应头中返回，请求权重最低的 ping 接口即可取得，429/418 响应同样带有用量
+        let url = format!("{}/api/v3/ping", Config::default().rest_api_endpoint);
+        let ping = async {
+            let response = self.http.get(url).send().await?;
+            for (name, value) in response.headers() {
+                if let Ok(value) = value.to_str() {
+                    self.limiter.sync_header(name.as_str(), value).await;
+                }
+            }
+            match response.status() {
+                s if s.is_success() => Ok(()),
+                s => Err(Error::Msg(format!("Received response: {s:?}"))),
+            }
+        };
+        self.limited(weight::PING, 0, Priority::Low, ping).await?;
+
+        let request = build_signed_request(Vec::<(&str, &str)>::new(), self.account.recv_window)?;
+        let counts: Vec<OrderCount> = self
+            .limited(
+                weight::ORDER_RATE_LIMIT,
+                0,
+                Priority::Low,
+                self.account
+                    .client
+                    .get_signed("/api/v3/rateLimit/order", &request),
+            )
+            .await?;
+        for count in counts {
+            let interval = interval_ms(&count.interval, count.interval_num);
+            self.limiter.sync(true, interval, count.count).await;
+        }
+        Ok(())
+    }
+
+    async fn subscribe(&self, streams: Arc<RwLock<Vec<String>>>, channels: Channels) -> Result<()> {
+        let resp = self
+            .limited(
+                weight::USER_STREAM,
+                0,
+                Priority::High,
+                self.user_stream.start(),
+            )
+            .await?;
+        tracing::info!("Join user stream");
+        let listen_key = Arc::new(RwLock::new(resp.listen_key));
+
+        let manager = StreamManager::new(streams, channels).with_listen_key(listen_key.clone());
+        let running = manager.running();
+        manager.spawn();
+
+        // listen key 60 分钟后失效，定期续期；续期失败时换新的 listen key 并重连
+        let user_stream = self.user_stream.clone();
+        let limiter = self.limiter.clone();
+        tokio::spawn(async move {
+            let mut tick = time::interval(KEEPALIVE_INTERVAL);
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                let key = { listen_key.read().await.clone() };
+                let renewed = limited(
+                    &limiter,
+                    weight::USER_STREAM,
+                    0,
+                    Priority::High,
+                    user_stream.keep_alive(&key),
+                )
+                .await;
+                match renewed {
+                    Ok(_) => {
+                        tracing::info!("Listen key renewed");
+                    }
+                    Err(e) => {
+                        tracing::error!("Renew listen key failed, {:?}", e);
+                        let started = limited(
+                            &limiter,
+                            weight::USER_STREAM,
+                            0,
+                            Priority::High,
+                            user_stream.start(),
+                        )
+                        .await;
+                        match started {
+                            Ok(resp) => {
+                                *listen_key.write().await = resp.listen_key;
+                                running.store(false, Ordering::Relaxed);
+                            }
+                            Err(e) => {
+                                tracing::error!("Restart user stream failed, {:?}", e);
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// 将数据流事件分发到对应的数据通道或订单通道。
+pub(crate) fn dispatch(event: WebsocketEventUntag, channels: &Channels) {
+    match event {
+        WebsocketEventUntag::WebsocketEvent(WebsocketEvent::OrderUpdate(order)) => {
+            METRICS
+                .stream_events
+                .with_label_values(&["user_data"])
+                .inc();
+            match channels.order.send(*order) {
+                Ok(()) => {
+                    tracing::info!("Send OrderUpdate Ok");
+                }
+                Err(e) => {
+                    tracing::error!("Send OrderUpdate failed: {:?}", e);
+                }
+            }
+        }
+        WebsocketEventUntag::WebsocketEvent(WebsocketEvent::Kline(kline)) => {
+            METRICS
+                .stream_events
+                .with_label_values(&[&kline_stream(
+                    &kline.symbol.to_lowercase(),
+                    &kline.kline.interval,
+                )])
+                .inc();
+            let data = channels.data.lock().unwrap();
+            let Some(data_tx) = data.get(&kline.data_index()) else {
+                return;
+            };
+            match data_tx.tx.send(Data::Kline(*kline)) {
+                Ok(size) => {
+                    tracing::info!("Send Kline, size: {:?}", size);
+                }
+                Err(e) => {
+                    tracing::error!("Send Kline failed,{:?}", e);
+                }
+            }
+        }
+        WebsocketEventUntag::BookTicker(bt) => {
+            METRICS
+                .stream_events
+                .with_label_values(&[&book_ticker_stream(&bt.symbol.to_lowercase())])
+                .inc();
+            let data = channels.data.lock().unwrap();
+            let Some(data_tx) = data.get(&bt.data_index()) else {
+                return;
+            };
+            match data_tx.tx.send(Data::BookTicker(*bt)) {
+                Ok(size) => {
+                    tracing::info!("Send BookTicker, size: {:?}", size);
+                }
+                Err(e) => {
+                    tracing::error!("Send BookTicker failed,{:?}", e);
+                }
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,394 @@
+use std::{fmt, time::Duration};
+
+use binance::{
+    errors::Error,
+    rest_model::{RateLimit, RateLimitInterval, RateLimitType},
+};
+use serde::Serialize;
+use tokio::{sync::Mutex, time};
+
+use crate::now;
+
+/// 低优先级请求可使用的额度比例，超出后直接丢弃
+const LOW_PRIORITY_RATIO: f64 = 0.8;
+/// 收到 429 后的退避时间，单位毫秒
+const RETRY_AFTER: u64 = 60_000;
+/// 收到 418（IP 被封禁）后的退避时间，单位毫秒
+const BAN_RETRY_AFTER: u64 = 300_000;
+/// 高优先级请求等待额度的上限，单位毫秒。请求在交易处理中发出，等待更久会卡住整个引擎
+const MAX_WAIT: u64 = 5_000;
+
+/// 请求权重，取自币安现货 REST 接口文档
+pub(crate) mod weight {
+    pub(crate) const PLACE_ORDER: u32 = 1;
+    pub(crate) const CANCEL_ORDER: u32 = 1;
+    pub(crate) const ORDER_STATUS: u32 = 4;
+    pub(crate) const OPEN_ORDERS: u32 = 6;
+    pub(crate) const ALL_ORDERS: u32 = 20;
+    pub(crate) const ACCOUNT: u32 = 20;
+    pub(crate) const EXCHANGE_INFO: u32 = 20;
+    pub(crate) const KLINES: u32 = 2;
+    pub(crate) const TICKER_PRICE: u32 = 2;
+    pub(crate) const USER_STREAM: u32 = 2;
+    pub(crate) const PING: u32 = 1;
+    pub(crate) const ORDER_RATE_LIMIT: u32 = 40;
+}
+
+/// 请求优先级：接近限额时高优先级请求短暂等待额度，低优先级请求直接丢弃。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Priority {
+    High,
+    Low,
+}
+
+/// 单个限额窗口的用量，窗口划分与币安响应头 `X-MBX-USED-WEIGHT-1M`、`X-MBX-ORDER-COUNT-10S` 等相同。
+/// 本地计数定期用交易所统计的用量校准，两次校准之间其他进程的请求不计入。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RateUsage {
+    pub name: String,
+    pub used: u32,
+    pub limit: u32,
+}
+
+impl fmt::Display for RateUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}/{}", self.name, self.used, self.limit)
+    }
+}
+
+/// 币安按固定窗口计数，窗口起点对齐到整秒、整分钟与 UTC 零点。
+#[derive(Debug)]
+struct Window {
+    orders: bool, // 下单次数窗口，否则为请求权重窗口
+    interval: u64,
+    limit: u32,
+    start: u64,
+    used: u32,
+}
+
+impl Window {
+    fn new(orders: bool, interval: u64, limit: u32) -> Self {
+        Self {
+            orders,
+            interval,
+            limit,
+            start: 0,
+            used: 0,
+        }
+    }
+
+    fn name(&self) -> String {
+        let kind = if self.orders {
+            "orders"
+        } else {
+            "request_weight"
+        };
+        let span = match self.interval {
+            i if i % 86_400_000 == 0 => format!("{}d", i / 86_400_000),
+            i if i % 60_000 == 0 => format!("{}m", i / 60_000),
+            i => format!("{}s", i / 1000),
+        };
+        format!("{}_{}", kind, span)
+    }
+
+    fn roll(&mut self, now: u64) {
+        let start = now - now % self.interval;
+        if start != self.start {
+            self.start = start;
+            self.used = 0;
+        }
+    }
+
+    fn cost(&self, weight: u32, orders: u32) -> u32 {
+        if self.orders {
+            orders
+        } else {
+            weight
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.start + self.interval
+    }
+}
+
+#[derive(Debug)]
+struct Limits {
+    windows: Vec<Window>,
+    backoff_until: u64, // 被限流或封禁后暂停请求直到该时刻
+}
+
+/// REST 请求限流器，所有账户请求共享：按请求权重与下单次数在本地计数，接近限额时等待或丢弃请求。
+///
+/// 限额来自交易规则中的 `rateLimits`，加载前使用币安现货的默认值。本地只统计经过本限流器的请求，
+/// 同一 IP 或账户下其他进程的用量通过 [`RateLimiter::sync`] 从交易所同步，实际被限流时依靠 429/418 响应退避。
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limits: Mutex<Limits>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            limits: Mutex::new(Limits {
+                windows: vec![
+                    Window::new(false, 60_000, 6000),
+                    Window::new(true, 10_000, 100),
+                    Window::new(true, 86_400_000, 200_000),
+                ],
+                backoff_until: 0,
+            }),
+        }
+    }
+}
+
+impl RateLimiter {
+    /// 使用交易所下发的限额替换默认值
+    pub(crate) async fn set_limits(&self, rate_limits: &[RateLimit]) {
+        let windows = rate_limits
+            .iter()
+            .filter_map(|limit| {
+                let orders = match limit.rate_limit_type {
+                    RateLimitType::RequestWeight => false,
+                    RateLimitType::Orders => true,
+                    _ => return None,
+                };
+                Some(Window::new(
+                    orders,
+                    interval_ms(&limit.interval, limit.interval_num as u64),
+                    limit.limit.max(0) as u32,
+                ))
+            })
+            .collect::<Vec<_>>();
+        if !windows.is_empty() {
+            self.limits.lock().await.windows = windows;
+        }
+    }
+
+    /// 占用一次请求的额度，`orders` 为计入下单次数的数量。
+    ///
+    /// 额度不足时高优先级请求等待到窗口重置，需等待超过 [`MAX_WAIT`]（如分钟窗口、日窗口耗尽或处于退避中）
+    /// 时返回错误，由调用方按下单失败处理；低优先级请求直接返回错误。
+    pub(crate) async fn acquire(
+        &self,
+        weight: u32,
+        orders: u32,
+        priority: Priority,
+    ) -> Result<(), Error> {
+        loop {
+            let wait = {
+                let mut limits = self.limits.lock().await;
+                let now = now();
+                match limits.take(weight, orders, priority, now) {
+                    Ok(()) => return Ok(()),
+                    Err(_) if priority == Priority::High => {}
+                    Err(e) => return Err(Error::Msg(e)),
+                }
+                let wait = limits.reset_at(weight, orders, now).saturating_sub(now);
+                if wait > MAX_WAIT {
+                    return Err(Error::Msg(format!(
+                        "Rate limit reached, quota resets in {}ms",
+                        wait
+                    )));
+                }
+                wait
+            };
+            tracing::info!("Rate limit reached, wait {}ms", wait);
+            time::sleep(Duration::from_millis(wait.max(1))).await;
+        }
+    }
+
+    /// 根据请求结果判断是否被限流，被限流时暂停后续请求
+    pub(crate) async fn on_result<T>(&self, result: &Result<T, Error>) {
+        let retry_after = match result {
+            Err(Error::Msg(msg)) if msg.ends_with("429") => RETRY_AFTER,
+            Err(Error::Msg(msg)) if msg.ends_with("418") => BAN_RETRY_AFTER,
+            Err(Error::BinanceError { response }) if response.code == -1003 => RETRY_AFTER,
+            _ => return,
+        };
+        tracing::error!("Request rate limited, back off {}ms", retry_after);
+        let mut limits = self.limits.lock().await;
+        limits.backoff_until = limits.backoff_until.max(now() + retry_after);
+    }
+
+    /// 用交易所统计的用量校准本地计数，`orders` 与 `interval` 指定窗口。
+    pub(crate) async fn sync(&self, orders: bool, interval: u64, used: u32) {
+        self.limits.lock().await.sync(orders, interval, used, now());
+    }
+
+    /// 按响应头 `X-MBX-USED-WEIGHT-1M`、`X-MBX-ORDER-COUNT-10S` 等校准本地计数，其他响应头忽略
+    pub(crate) async fn sync_header(&self, name: &str, value: &str) {
+        if let Some((orders, interval, used)) = parse_header(name, value) {
+            self.sync(orders, interval, used).await;
+        }
+    }
+
+    /// 各窗口的当前用量
+    pub(crate) async fn usage(&self) -> Vec<RateUsage> {
+        let mut limits = self.limits.lock().await;
+        let now = now();
+        limits
+            .windows
+            .iter_mut()
+            .map(|w| {
+                w.roll(now);
+                RateUsage {
+                    name: w.name(),
+                    used: w.used,
+                    limit: w.limit,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Limits {
+    // 交易所的计数包含同一 IP 或账户下其他进程的请求，只在高于本地计数时采用
+    fn sync(&mut self, orders: bool, interval: u64, used: u32, now: u64) {
+        let window = self
+            .windows
+            .iter_mut()
+            .find(|w| w.orders == orders && w.interval == interval);
+        if let Some(w) = window {
+            w.roll(now);
+            w.used = w.used.max(used);
+        }
+    }
+
+    fn take(
+        &mut self,
+        weight: u32,
+        orders: u32,
+        priority: Priority,
+        now: u64,
+    ) -> Result<(), String> {
+        if now < self.backoff_until {
+            return Err(format!("Rate limited until {}", self.backoff_until));
+        }
+        let ratio = match priority {
+            Priority::High => 1.,
+            Priority::Low => LOW_PRIORITY_RATIO,
+        };
+        for w in self.windows.iter_mut() {
+            w.roll(now);
+            let cost = w.cost(weight, orders);
+            if cost > 0 && (w.used + cost) as f64 > w.limit as f64 * ratio {
+                return Err(format!(
+                    "Rate limit {} {}/{} reached",
+                    w.name(),
+                    w.used,
+                    w.limit
+                ));
+            }
+        }
+        for w in self.windows.iter_mut() {
+            w.used += w.cost(weight, orders);
+        }
+        Ok(())
+    }
+
+    /// 额度恢复的时刻：退避结束，且所有不足的窗口都已重置
+    fn reset_at(&self, weight: u32, orders: u32, now: u64) -> u64 {
+        self.windows
+            .iter()
+            .filter(|w| {
+                let cost = w.cost(weight, orders);
+                cost > 0 && w.used + cost > w.limit
+            })
+            .map(Window::end)
+            .fold(self.backoff_until.max(now), u64::max)
+    }
+}
+
+/// 限额窗口的长度，单位毫秒
+pub(crate) fn interval_ms(interval: &RateLimitInterval, num: u64) -> u64 {
+    let unit = match interval {
+        RateLimitInterval::Second => 1000,
+        RateLimitInterval::Minute => 60_000,
+        RateLimitInterval::Day => 86_400_000,
+    };
+    unit * num.max(1)
+}
+
+// 解析用量响应头，返回 (是否为下单次数, 窗口长度, 用量)
+fn parse_header(name: &str, value: &str) -> Option<(bool, u64, u32)> {
+    let name = name.to_ascii_lowercase();
+    let (orders, span) = if let Some(span) = name.strip_prefix("x-mbx-used-weight-") {
+        (false, span)
+    } else {
+        (true, name.strip_prefix("x-mbx-order-count-")?)
+    };
+    Some((orders, parse_interval(span)?, value.parse().ok()?))
+}
+
+// 响应头中的窗口长度，例如 `1m`、`10s`、`1d`，单位毫秒
+fn parse_interval(span: &str) -> Option<u64> {
+    let unit = match span.chars().last()? {
+        's' => 1000,
+        'm' => 60_000,
+        'h' => 3_600_000,
+        'd' => 86_400_000,
+        _ => return None,
+    };
+    let num = span[..span.len() - 1].parse::<u64>().ok()?;
+    Some(unit * num)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_header, Limits, Priority, Window};
+
+    #[test]
+    fn test_limits() {
+        let mut limits = Limits {
+            windows: vec![Window::new(false, 60_000, 10), Window::new(true, 10_000, 2)],
+            backoff_until: 0,
+        };
+
+        // 低优先级只能用到 80% 的额度
+        limits.take(8, 0, Priority::Low, 0).unwrap();
+        assert!(limits.take(1, 0, Priority::Low, 1).is_err());
+        limits.take(1, 1, Priority::High, 1).unwrap();
+        limits.take(1, 1, Priority::High, 2).unwrap();
+        assert!(limits.take(0, 1, Priority::High, 3).is_err());
+        assert_eq!(limits.reset_at(0, 1, 3), 10_000);
+        assert_eq!(limits.reset_at(1, 0, 3), 60_000);
+
+        // 下单次数窗口重置后可继续下单
+        limits.take(0, 1, Priority::High, 10_000).unwrap();
+        assert_eq!(limits.windows[0].used, 10);
+        assert_eq!(limits.windows[1].used, 1);
+
+        limits.backoff_until = 70_000;
+        assert!(limits.take(1, 0, Priority::High, 60_000).is_err());
+        assert_eq!(limits.reset_at(1, 0, 60_000), 70_000);
+        limits.take(1, 0, Priority::High, 70_000).unwrap();
+        assert_eq!(limits.windows[0].used, 1);
+    }
+
+    #[test]
+    fn test_sync() {
+        assert_eq!(
+            parse_header("X-MBX-USED-WEIGHT-1M", "120"),
+            Some((false, 60_000, 120))
+        );
+        assert_eq!(
+            parse_header("x-mbx-order-count-10s", "3"),
+            Some((true, 10_000, 3))
+        );
+        assert_eq!(parse_header("x-mbx-used-weight", "120"), None);
+        assert_eq!(parse_header("content-length", "2"), None);
+
+        // 交易所的计数只在高于本地计数时采用
+        let mut limits = Limits {
+            windows: vec![Window::new(false, 60_000, 10), Window::new(true, 10_000, 2)],
+            backoff_until: 0,
+        };
+        limits.take(3, 1, Priority::High, 0).unwrap();
+        limits.sync(false, 60_000, 8, 1);
+        limits.sync(true, 10_000, 0, 1);
+        assert_eq!(limits.windows[0].used, 8);
+        assert_eq!(limits.windows[1].used, 1);
+        assert!(limits.take(3, 0, Priority::High, 2).is_err());
+    }
+}
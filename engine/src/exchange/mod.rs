@@ -0,0 +1,180 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use binance::{
+    account::{OrderCancellation, OrderRequest, OrderStatusRequest},
+    errors::Result,
+    rest_model::{Balance, ExchangeInformation, KlineSummary, Order, OrderCanceled, Transaction},
+    ws_model::OrderUpdate,
+};
+use strategies::KlineInterval;
+use tokio::sync::{mpsc::UnboundedSender, RwLock};
+
+pub use self::{binance_spot::BinanceSpot, limiter::RateUsage, paper::Paper};
+use crate::{channel::Broadcast, DataChannels};
+
+mod binance_spot;
+mod limiter;
+pub(crate) mod paper;
+mod stream;
+
+/// 交易所推送数据的去处：行情进入数据通道，订单回报进入订单通道，断线重连后通知数据缺口，
+/// 每次重连后通知可能丢失的订单回报，
+/// 订阅的数据流变化后收到通知重新订阅，收到停止通知后断开数据流。
+#[derive(Clone)]
+pub struct Channels {
+    pub(crate) data: DataChannels,
+    pub(crate) order: UnboundedSender<OrderUpdate>,
+    pub(crate) gap: Broadcast<()>,
+    pub(crate) reconnected: Broadcast<()>,
+    pub(crate) resubscribe: Broadcast<()>,
+    pub(crate) stop: Broadcast<()>,
+}
+
+/// 交易所抽象，引擎只通过该接口下单、撤单、查询与订阅数据。
+#[async_trait]
+pub trait Exchange {
+    /// 下单
+    async fn place_order(&self, order: OrderRequest) -> Result<Transaction>;
+
+    /// 撤单
+    async fn cancel_order(&self, cancellation: OrderCancellation) -> Result<OrderCanceled>;
+
+    /// 查询单个订单
+    async fn order_status(&self, request: OrderStatusRequest) -> Result<Order>;
+
+    /// 查询交易对的当前挂单
+    async fn open_orders(&self, symbol: &str) -> Result<Vec<Order>>;
+
+    /// 查询交易对最近的订单，包含已成交与已撤销的订单
+    async fn all_orders(&self, symbol: &str) -> Result<Vec<Order>>;
+
+    /// 查询资产余额
+    async fn balance(&self, asset: &str) -> Result<Balance>;
+
+    /// 查询交易规则与交易对信息
+    async fn exchange_info(&self) -> Result<ExchangeInformation>;
+
+    /// 查询最近的 `limit` 根K线，按时间升序，最后一根可能尚未收盘。
+    async fn klines(
+        &self,
+        symbol: &str,
+        interval: &KlineInterval,
+        limit: u16,
+    ) -> Result<Vec<KlineSummary>>;
+
+    /// 查询交易对的最新成交价
+    async fn price(&self, symbol: &str) -> Result<f64>;
+
+    /// REST 请求额度的当前用量，不限流的交易所返回空
+    async fn rate_usage(&self) -> Vec<RateUsage> {
+        Vec::new()
+    }
+
+    /// 从交易所同步请求额度的用量，计入同一 IP 或账户下其他进程的请求
+    async fn sync_rate_usage(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// 订阅行情与用户数据流，数据通过 `channels` 推送给引擎。`streams` 变化后通过
+    /// `channels.resubscribe` 通知重新订阅。
+    async fn subscribe(&self, streams: Arc<RwLock<Vec<String>>>, channels: Channels) -> Result<()>;
+}
+
+/// 使用枚举而非泛型持有交易所，避免动态分发。
+pub enum Exchanges {
+    BinanceSpot(BinanceSpot),
+    Paper(Paper),
+}
+
+#[async_trait]
+impl Exchange for Exchanges {
+    async fn place_order(&self, order: OrderRequest) -> Result<Transaction> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.place_order(order).await,
+            Exchanges::Paper(e) => e.place_order(order).await,
+        }
+    }
+
+    async fn cancel_order(&self, cancellation: OrderCancellation) -> Result<OrderCanceled> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.cancel_order(cancellation).await,
+            Exchanges::Paper(e) => e.cancel_order(cancellation).await,
+        }
+    }
+
+    async fn order_status(&self, request: OrderStatusRequest) -> Result<Order> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.order_status(request).await,
+            Exchanges::Paper(e) => e.order_status(request).await,
+        }
+    }
+
+    async fn open_orders(&self, symbol: &str) -> Result<Vec<Order>> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.open_orders(symbol).await,
+            Exchanges::Paper(e) => e.open_orders(symbol).await,
+        }
+    }
+
+    async fn all_orders(&self, symbol: &str) -> Result<Vec<Order>> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.all_orders(symbol).await,
+            Exchanges::Paper(e) => e.all_orders(symbol).await,
+        }
+    }
+
+    async fn balance(&self, asset: &str) -> Result<Balance> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.balance(asset).await,
+            Exchanges::Paper(e) => e.balance(asset).await,
+        }
+    }
+
+    async fn exchange_info(&self) -> Result<ExchangeInformation> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.exchange_info().await,
+            Exchanges::Paper(e) => e.exchange_info().await,
+        }
+    }
+
+    async fn klines(
+        &self,
+        symbol: &str,
+        interval: &KlineInterval,
+        limit: u16,
+    ) -> Result<Vec<KlineSummary>> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.klines(symbol, interval, limit).await,
+            Exchanges::Paper(e) => e.klines(symbol, interval, limit).await,
+        }
+    }
+
+    async fn price(&self, symbol: &str) -> Result<f64> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.price(symbol).await,
+            Exchanges::Paper(e) => e.price(symbol).await,
+        }
+    }
+
+    async fn rate_usage(&self) -> Vec<RateUsage> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.rate_usage().await,
+            Exchanges::Paper(e) => e.rate_usage().await,
+        }
+    }
+
+    async fn sync_rate_usage(&self) -> Result<()> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.sync_rate_usage().await,
+            Exchanges::Paper(e) => e.sync_rate_usage().await,
+        }
+    }
+
+    async fn subscribe(&self, streams: Arc<RwLock<Vec<String>>>, channels: Channels) -> Result<()> {
+        match self {
+            Exchanges::BinanceSpot(e) => e.subscribe(streams, channels).await,
+            Exchanges::Paper(e) => e.subscribe(streams, channels).await,
+        }
+    }
+}
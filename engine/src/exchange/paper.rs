@@ -0,0 +1,668 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use binance::{
+    account::{OrderCancellation, OrderRequest, OrderStatusRequest},
+    api::Binance,
+    errors::{BinanceContentError, Error, Result},
+    general::General,
+    market::Market,
+    rest_model::{
+        Balance, ExchangeInformation, KlineSummaries, KlineSummary, Order, OrderCanceled,
+        OrderSide, OrderStatus, OrderType, TimeInForce, Transaction,
+    },
+    ws_model::OrderUpdate,
+};
+use strategies::{Data, KlineInterval};
+use tokio::sync::{broadcast::error::RecvError, mpsc::UnboundedSender, RwLock};
+
+use super::{binance_spot, stream::StreamManager, Channels, Exchange};
+use crate::{instance::ORDER_NOT_FOUND, now, FEE_RATE};
+
+/// 常见计价资产，用于拆分交易对。
+const QUOTE_ASSETS: [&str; 7] = ["USDT", "BUSD", "USDC", "TUSD", "BTC", "ETH", "BNB"];
+
+/// 模拟盘：行情来自真实数据流，订单在进程内撮合，不动用真实资金。
+pub struct Paper {
+    book: Arc<RwLock<Book>>,
+    general: General, // 交易规则取自真实交易所
+    market: Market,   // 历史K线取自真实交易所
+}
+
+impl Paper {
+    /// `quote_asset` 为计价资产，`principal` 为模拟账户的初始资金。
+    pub fn new(quote_asset: &str, principal: f64) -> Self {
+        let mut book = Book::default();
+        book.balance_mut(quote_asset).free = principal;
+        Self {
+            book: Arc::new(RwLock::new(book)),
+            general: General::new(None, None),
+            market: Market::new(None, None),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Paper {
+    /// 以 `price` 成交订单的 `qty`，模拟部分成交
+    pub(crate) async fn trade(&self, order_id: u64, qty: f64, price: f64) {
+        self.book.write().await.trade(order_id, qty, price);
+    }
+
+    /// 设置交易对的最新成交价，市价单按此成交
+    pub(crate) async fn set_price(&self, symbol: &str, price: f64) {
+        self.book
+            .write()
+            .await
+            .last_prices
+            .insert(symbol.to_string(), price);
+    }
+}
+
+#[async_trait]
+impl Exchange for Paper {
+    async fn place_order(&self, order: OrderRequest) -> Result<Transaction> {
+        self.book.write().await.place(order)
+    }
+
+    async fn cancel_order(&self, cancellation: OrderCancellation) -> Result<OrderCanceled> {
+        self.book.write().await.cancel(cancellation)
+    }
+
+    async fn order_status(&self, request: OrderStatusRequest) -> Result<Order> {
+        let book = self.book.read().await;
+        book.find(request.order_id, request.orig_client_order_id.as_deref())
+            .map(PaperOrder::to_order)
+            .ok_or_else(order_not_found)
+    }
+
+    async fn open_orders(&self, symbol: &str) -> Result<Vec<Order>> {
+        let book = self.book.read().await;
+        Ok(book
+            .orders
+            .values()
+            .filter(|o| o.symbol == symbol && o.is_open())
+            .map(PaperOrder::to_order)
+            .collect())
+    }
+
+    async fn all_orders(&self, symbol: &str) -> Result<Vec<Order>> {
+        let book = self.book.read().await;
+        let mut orders = book
+            .orders
+            .values()
+            .filter(|o| o.symbol == symbol)
+            .map(PaperOrder::to_order)
+            .collect::<Vec<_>>();
+        orders.sort_by_key(|o| o.order_id);
+        Ok(orders)
+    }
+
+    async fn balance(&self, asset: &str) -> Result<Balance> {
+        let book = self.book.read().await;
+        Ok(book.balances.get(asset).cloned().unwrap_or(Balance {
+            asset: asset.to_string(),
+            free: 0.,
+            locked: 0.,
+        }))
+    }
+
+    async fn exchange_info(&self) -> Result<ExchangeInformation> {
+        binance_spot::exchange_info(&self.general).await
+    }
+
+    async fn klines(
+        &self,
+        symbol: &str,
+        interval: &KlineInterval,
+        limit: u16,
+    ) -> Result<Vec<KlineSummary>> {
+        let KlineSummaries::AllKlineSummaries(klines) = self
+            .market
+            .get_klines(symbol, interval.to_string(), limit, None, None)
+            .await?;
+        Ok(klines)
+    }
+
+    async fn price(&self, symbol: &str) -> Result<f64> {
+        Ok(self.market.get_price(symbol).await?.price)
+    }
+
+    async fn subscribe(&self, streams: Arc<RwLock<Vec<String>>>, channels: Channels) -> Result<()> {
+        self.book.write().await.order_tx = Some(channels.order.clone());
+
+        // 用引擎的数据通道撮合挂单，重新订阅时补上新增的数据通道
+        let book = self.book.clone();
+        let data = channels.data.clone();
+        let mut resubscribe_rx = channels.resubscribe.tx.subscribe();
+        tokio::spawn(async move {
+            let mut watched = HashSet::new();
+            loop {
+                let added = {
+                    let data = data.lock().unwrap();
+                    watched.retain(|index| data.contains_key(index));
+                    data.iter()
+                        .filter(|(index, _)| !watched.contains(*index))
+                        .map(|(index, data_tx)| (index.clone(), data_tx.tx.subscribe()))
+                        .collect::<Vec<_>>()
+                };
+                for (index, mut data_rx) in added {
+                    watched.insert(index);
+                    let book = book.clone();
+                    tokio::spawn(async move {
+                        loop {
+                            match data_rx.recv().await {
+                                Ok(data) => book.write().await.on_data(&data),
+                                Err(RecvError::Closed) => break,
+                                Err(err) => {
+                                    tracing::info!("Paper recv Data failed, {:?}", err);
+                                }
+                            }
+                        }
+                    });
+                }
+                if let Err(RecvError::Closed) = resubscribe_rx.recv().await {
+                    break;
+                }
+            }
+        });
+
+        // 模拟盘只需要行情，不订阅用户数据流
+        StreamManager::new(streams, channels).spawn();
+        Ok(())
+    }
+}
+
+struct PaperOrder {
+    symbol: String,
+    order_id: u64,
+    client_order_id: String,
+    side: OrderSide,
+    order_type: OrderType,
+    price: f64,
+    qty: f64,
+    filled_qty: f64,
+    cummulative_quote_qty: f64,
+    status: OrderStatus,
+    time: u64,
+    update_time: u64,
+}
+
+impl PaperOrder {
+    fn is_open(&self) -> bool {
+        matches!(self.status, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
+
+    fn to_order(&self) -> Order {
+        Order {
+            symbol: self.symbol.clone(),
+            order_id: self.order_id,
+            order_list_id: -1,
+            client_order_id: self.client_order_id.clone(),
+            price: self.price,
+            orig_qty: self.qty,
+            executed_qty: self.filled_qty,
+            cummulative_quote_qty: self.cummulative_quote_qty,
+            status: self.status.clone(),
+            time_in_force: TimeInForce::GTC,
+            order_type: self.order_type.clone(),
+            side: self.side.clone(),
+            stop_price: 0.,
+            iceberg_qty: 0.,
+            time: self.time,
+            update_time: self.update_time,
+            is_working: self.is_open(),
+            orig_quote_order_qty: 0.,
+        }
+    }
+
+    fn to_transaction(&self) -> Transaction {
+        Transaction {
+            symbol: self.symbol.clone(),
+            order_id: self.order_id,
+            client_order_id: self.client_order_id.clone(),
+            transact_time: self.update_time,
+            price: self.price,
+            orig_qty: self.qty,
+            executed_qty: self.filled_qty,
+            cummulative_quote_qty: self.cummulative_quote_qty,
+            status: self.status.clone(),
+            time_in_force: TimeInForce::GTC,
+            order_type: self.order_type.clone(),
+            side: self.side.clone(),
+            fills: vec![],
+        }
+    }
+}
+
+#[derive(Default)]
+struct Book {
+    next_id: u64,
+    next_trade_id: i64,
+    orders: HashMap<u64, PaperOrder>,
+    balances: HashMap<String, Balance>,
+    last_prices: HashMap<String, f64>,
+    order_tx: Option<UnboundedSender<OrderUpdate>>,
+}
+
+#[allow(clippy::result_large_err)]
+impl Book {
+    fn balance_mut(&mut self, asset: &str) -> &mut Balance {
+        self.balances
+            .entry(asset.to_string())
+            .or_insert_with(|| Balance {
+                asset: asset.to_string(),
+                free: 0.,
+                locked: 0.,
+            })
+    }
+
+    fn find(&self, order_id: Option<u64>, client_order_id: Option<&str>) -> Option<&PaperOrder> {
+        match order_id {
+            Some(id) => self.orders.get(&id),
+            None => self
+                .orders
+                .values()
+                .find(|o| Some(o.client_order_id.as_str()) == client_order_id),
+        }
+    }
+
+    fn place(&mut self, req: OrderRequest) -> Result<Transaction> {
+        let (base, quote) =
+            split_symbol(&req.symbol).ok_or_else(|| Error::UnknownSymbol(req.symbol.clone()))?;
+        let price = match req.order_type {
+            OrderType::Market => *self
+                .last_prices
+                .get(&req.symbol)
+                .ok_or_else(|| Error::Msg(format!("No market price for {}", req.symbol)))?,
+            _ => req
+                .price
+                .ok_or_else(|| Error::Msg("Limit order without price".to_string()))?,
+        };
+        let qty = req
+            .quantity
+            .or_else(|| req.quote_order_qty.map(|q| q / price))
+            .ok_or_else(|| Error::Msg("Order without quantity".to_string()))?;
+
+        // 冻结资金
+        let (asset, amount) = match req.side {
+            OrderSide::Buy => (quote, price * qty),
+            OrderSide::Sell => (base, qty),
+        };
+        let balance = self.balance_mut(asset);
+        if balance.free < amount {
+            return Err(Error::Msg(format!(
+                "Insufficient {} balance, need {}, free {}",
+                asset, amount, balance.free
+            )));
+        }
+        balance.free -= amount;
+        balance.locked += amount;
+
+        self.next_id += 1;
+        let now = now();
+        let order = PaperOrder {
+            symbol: req.symbol,
+            order_id: self.next_id,
+            client_order_id: req
+                .new_client_order_id
+                .unwrap_or_else(|| format!("paper-{}", self.next_id)),
+            side: req.side,
+            order_type: req.order_type,
+            price,
+            qty,
+            filled_qty: 0.,
+            cummulative_quote_qty: 0.,
+            status: OrderStatus::New,
+            time: now,
+            update_time: now,
+        };
+        self.notify(&order, OrderStatus::New, 0., 0., 0., None);
+
+        let order_id = order.order_id;
+        let is_market = order.order_type == OrderType::Market;
+        self.orders.insert(order_id, order);
+        if is_market {
+            self.fill(order_id, price);
+        }
+        Ok(self.orders[&order_id].to_transaction())
+    }
+
+    fn cancel(&mut self, c: OrderCancellation) -> Result<OrderCanceled> {
+        let order_id = self
+            .find(c.order_id, c.orig_client_order_id.as_deref())
+            .filter(|o| o.is_open())
+            .map(|o| o.order_id)
+            .ok_or_else(|| Error::Msg(format!("Unknown order, {:?}", c)))?;
+
+        let mut order = self.orders.remove(&order_id).unwrap();
+        // 解冻剩余资金
+        let (base, quote) = split_symbol(&order.symbol).unwrap();
+        let remaining = order.qty - order.filled_qty;
+        let (asset, amount) = match order.side {
+            OrderSide::Buy => (quote, order.price * remaining),
+            OrderSide::Sell => (base, remaining),
+        };
+        let balance = self.balance_mut(asset);
+        balance.locked -= amount;
+        balance.free += amount;
+
+        order.status = OrderStatus::Canceled;
+        order.update_time = now();
+        self.notify(&order, OrderStatus::Canceled, 0., 0., 0., None);
+
+        let canceled = OrderCanceled {
+            symbol: order.symbol.clone(),
+            orig_client_order_id: order.client_order_id.clone(),
+            order_id: order.order_id,
+            client_order_id: c.new_client_order_id.unwrap_or_default(),
+        };
+        self.orders.insert(order_id, order);
+        Ok(canceled)
+    }
+
+    /// 以 `price` 成交订单的剩余数量。
+    fn fill(&mut self, order_id: u64, price: f64) {
+        self.trade(order_id, f64::INFINITY, price);
+    }
+
+    /// 以 `price` 成交订单的 `qty`，超出剩余数量时只成交剩余部分，手续费从收到的资产中扣除。
+    fn trade(&mut self, order_id: u64, qty: f64, price: f64) {
+        let Some(mut order) = self.orders.remove(&order_id) else {
+            return;
+        };
+        let (base, quote) = split_symbol(&order.symbol).unwrap();
+        let qty = qty.min(order.qty - order.filled_qty);
+        let quote_qty = qty * price;
+
+        let (commission, commission_asset) = match order.side {
+            OrderSide::Buy => {
+                let commission = qty * FEE_RATE;
+                let locked = order.price * qty;
+                let balance = self.balance_mut(quote);
+                balance.locked -= locked;
+                balance.free += locked - quote_qty;
+                self.balance_mut(base).free += qty - commission;
+                (commission, base)
+            }
+            OrderSide::Sell => {
+                let commission = quote_qty * FEE_RATE;
+                self.balance_mut(base).locked -= qty;
+                self.balance_mut(quote).free += quote_qty - commission;
+                (commission, quote)
+            }
+        };
+
+        order.filled_qty += qty;
+        order.cummulative_quote_qty += quote_qty;
+        order.status = if order.filled_qty < order.qty {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::Filled
+        };
+        order.update_time = now();
+        self.next_trade_id += 1;
+        self.notify(
+            &order,
+            OrderStatus::Trade,
+            qty,
+            price,
+            commission,
+            Some(commission_asset.to_string()),
+        );
+        self.orders.insert(order_id, order);
+    }
+
+    /// 用最新行情撮合挂单：K线用最高、最低价，盘口用买一、卖一价。
+    ///
+    /// K线的最高、最低价可能出现在挂单之前，只撮合K线开盘前已挂出的订单，其余订单等盘口或下一根K线。
+    fn on_data(&mut self, data: &Data) {
+        let (symbol, ask, bid, last, since) = match data {
+            Data::Kline(k) => (
+                &k.symbol,
+                k.kline.low,
+                k.kline.high,
+                k.kline.close,
+                k.kline.start_time.max(0) as u64,
+            ),
+            Data::BookTicker(b) => (
+                &b.symbol,
+                b.best_ask,
+                b.best_bid,
+                (b.best_ask + b.best_bid) / 2.,
+                u64::MAX,
+            ),
+        };
+        self.last_prices.insert(symbol.clone(), last);
+
+        let matched = self
+            .orders
+            .values()
+            .filter(|o| &o.symbol == symbol && o.is_open() && o.time <= since)
+            .filter(|o| match o.side {
+                OrderSide::Buy => ask <= o.price,
+                OrderSide::Sell => bid >= o.price,
+            })
+            .map(|o| (o.order_id, o.price))
+            .collect::<Vec<_>>();
+        for (order_id, price) in matched {
+            self.fill(order_id, price);
+        }
+    }
+
+    fn notify(
+        &self,
+        order: &PaperOrder,
+        execution_type: OrderStatus,
+        last_qty: f64,
+        last_price: f64,
+        commission: f64,
+        commission_asset: Option<String>,
+    ) {
+        let Some(order_tx) = &self.order_tx else {
+            return;
+        };
+        // 与交易所一致，只有成交回报带成交ID
+        let trade_id = match execution_type {
+            OrderStatus::Trade => self.next_trade_id,
+            _ => -1,
+        };
+        let update = OrderUpdate {
+            event_time: order.update_time,
+            symbol: order.symbol.clone(),
+            client_order_id: Some(order.client_order_id.clone()),
+            side: order.side.clone(),
+            order_type: order.order_type.clone(),
+            time_in_force: TimeInForce::GTC,
+            qty: order.qty,
+            price: order.price,
+            stop_price: 0.,
+            iceberg_qty: 0.,
+            order_list_id: -1,
+            origin_client_id: None,
+            execution_type,
+            current_order_status: order.status.clone(),
+            order_reject_reason: "NONE".to_string(),
+            order_id: order.order_id,
+            qty_last_executed: last_qty,
+            cumulative_filled_qty: order.filled_qty,
+            last_executed_price: last_price,
+            commission,
+            commission_asset,
+            trade_order_time: order.update_time,
+            trade_id,
+            i_ignore: 0,
+            is_order_on_the_book: order.is_open(),
+            is_buyer_maker: false,
+            m_ignore: false,
+            order_creation_time: order.time,
+            cumulative_quote_asset_transacted_qty: order.cummulative_quote_qty,
+            last_quote_asset_transacted_qty: last_qty * last_price,
+            quote_order_qty: 0.,
+        };
+        if let Err(e) = order_tx.send(update) {
+            tracing::error!("Send paper OrderUpdate failed: {:?}", e);
+        }
+    }
+}
+
+// 与交易所相同的订单不存在错误，调用方据此判断订单未提交
+fn order_not_found() -> Error {
+    match serde_json::from_value::<BinanceContentError>(serde_json::json!({
+        "code": ORDER_NOT_FOUND,
+        "msg": "Order does not exist.",
+    })) {
+        Ok(response) => Error::BinanceError { response },
+        Err(e) => Error::Json(e),
+    }
+}
+
+/// 拆分交易对为 (基础资产, 计价资产)。
+pub(crate) fn split_symbol(symbol: &str) -> Option<(&str, &str)> {
+    QUOTE_ASSETS
+        .iter()
+        .find(|quote| symbol.len() > quote.len() && symbol.ends_with(*quote))
+        .map(|quote| symbol.split_at(symbol.len() - quote.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use binance::{
+        account::{OrderRequest, OrderStatusRequest},
+        rest_model::{OrderSide, OrderStatus, OrderType},
+        ws_model::{BookTickerEvent, Kline, KlineEvent},
+    };
+    use strategies::Data;
+    use tokio::sync::mpsc;
+
+    use super::{Book, Paper};
+    use crate::{
+        exchange::Exchange,
+        instance::{is_order_missing, ORDER_NOT_FOUND},
+        now,
+    };
+
+    fn kline(start_time: u64, low: f64, high: f64, close: f64) -> Data {
+        Data::Kline(KlineEvent {
+            event_time: 1,
+            symbol: "BTCUSDT".to_string(),
+            kline: Kline {
+                start_time: start_time as i64,
+                end_time: start_time as i64 + 3_599_999,
+                symbol: "BTCUSDT".to_string(),
+                interval: "1h".to_string(),
+                first_trade_id: 0,
+                last_trade_id: 0,
+                open: close,
+                close,
+                high,
+                low,
+                volume: 0.,
+                number_of_trades: 0,
+                is_final_bar: true,
+                quote_volume: 0.,
+                active_buy_volume: 0.,
+                active_volume_buy_quote: 0.,
+                ignore_me: String::new(),
+            },
+        })
+    }
+
+    #[test]
+    fn test_limit_buy_fill() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut book = Book::default();
+        book.balance_mut("USDT").free = 100.;
+        book.order_tx = Some(tx);
+
+        book.place(OrderRequest {
+            symbol: "BTCUSDT".to_string(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            quantity: Some(1.),
+            price: Some(90.),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(book.balances["USDT"].locked, 90.);
+        let placed = book.orders[&1].time;
+
+        book.on_data(&kline(placed + 1, 95., 110., 100.));
+        assert_eq!(
+            rx.try_recv().unwrap().current_order_status,
+            OrderStatus::New
+        );
+        assert!(rx.try_recv().is_err());
+
+        // 挂单前开盘的K线，最低价可能发生在挂单之前，不撮合
+        book.on_data(&kline(0, 85., 100., 95.));
+        assert!(rx.try_recv().is_err());
+
+        book.on_data(&kline(placed + 1, 85., 100., 95.));
+        let update = rx.try_recv().unwrap();
+        assert_eq!(update.current_order_status, OrderStatus::Filled);
+        assert_eq!(update.last_executed_price, 90.);
+        assert_eq!(book.balances["USDT"].free, 10.);
+        assert_eq!(book.balances["BTC"].free, 1. - update.commission);
+    }
+
+    #[test]
+    fn test_limit_sell_book_ticker() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut book = Book::default();
+        book.balance_mut("BTC").free = 1.;
+        book.order_tx = Some(tx);
+
+        book.place(OrderRequest {
+            symbol: "BTCUSDT".to_string(),
+            side: OrderSide::Sell,
+            order_type: OrderType::Limit,
+            quantity: Some(1.),
+            price: Some(110.),
+            ..Default::default()
+        })
+        .unwrap();
+        rx.try_recv().unwrap();
+
+        // 盘口是挂单之后的实时报价，直接撮合
+        let ticker = |bid: f64| {
+            Data::BookTicker(BookTickerEvent {
+                update_id: now(),
+                symbol: "BTCUSDT".to_string(),
+                best_bid: bid,
+                best_bid_qty: 1.,
+                best_ask: bid + 0.01,
+                best_ask_qty: 1.,
+            })
+        };
+        book.on_data(&ticker(109.));
+        assert!(rx.try_recv().is_err());
+        book.on_data(&ticker(110.));
+        let update = rx.try_recv().unwrap();
+        assert_eq!(update.current_order_status, OrderStatus::Filled);
+        assert_eq!(update.last_executed_price, 110.);
+    }
+
+    #[tokio::test]
+    async fn test_order_missing() {
+        let paper = Paper::new("USDT", 100.);
+        let err = paper
+            .order_status(OrderStatusRequest {
+                symbol: "BTCUSDT".to_string(),
+                orig_client_order_id: Some("bqinst1-1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        // 下单重试依赖与交易所一致的错误码判断订单未提交
+        assert!(
+            is_order_missing(&err),
+            "{:?} is not {}",
+            err,
+            ORDER_NOT_FOUND
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use binance::{
+    websockets::WebSockets,
+    ws_model::{CombinedStreamEvent, WebsocketEventUntag},
+};
+use tokio::{
+    sync::{broadcast::error::RecvError, RwLock},
+    time,
+};
+
+use super::{binance_spot::dispatch, Channels};
+use crate::metrics::METRICS;
+
+/// 重连的初始退避时间
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+/// 重连的最大退避时间
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// 连接保持超过该时长视为稳定，退避时间复位
+const STABLE_DURATION: Duration = Duration::from_secs(60);
+
+/// 组合数据流管理：在后台维持连接，断线后按指数退避重连并重新订阅全部数据流，
+/// 重连成功后通知策略断线期间的数据缺口。
+pub(crate) struct StreamManager {
+    streams: Arc<RwLock<Vec<String>>>,       // 每次连接时读取最新值
+    listen_key: Option<Arc<RwLock<String>>>, // 用户数据流，每次连接时读取最新值
+    running: Arc<AtomicBool>,                // 置为 false 时断开当前连接并立即重连
+    channels: Channels,
+}
+
+impl StreamManager {
+    pub(crate) fn new(streams: Arc<RwLock<Vec<String>>>, channels: Channels) -> Self {
+        Self {
+            streams,
+            listen_key: None,
+            running: Arc::new(AtomicBool::new(true)),
+            channels,
+        }
+    }
+
+    pub(crate) fn with_listen_key(mut self, listen_key: Arc<RwLock<String>>) -> Self {
+        self.listen_key = Some(listen_key);
+        self
+    }
+
+    /// 用于主动触发重连，例如 listen key 更换后。
+    pub(crate) fn running(&self) -> Arc<AtomicBool> {
+        self.running.clone()
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn spawn(self) {
+        // 数据流变化后断开当前连接，立即按新的数据流重连，不视为数据缺口
+        let resubscribing = Arc::new(AtomicBool::new(false));
+        tokio::spawn({
+            let mut resubscribe_rx = self.channels.resubscribe.tx.subscribe();
+            let resubscribing = resubscribing.clone();
+            let running = self.running.clone();
+            async move {
+                while !matches!(resubscribe_rx.recv().await, Err(RecvError::Closed)) {
+                    resubscribing.store(true, Ordering::Relaxed);
+                    running.store(false, Ordering::Relaxed);
+                }
+            }
+        });
+        // 收到停止通知后断开当前连接，不再重连
+        let stopped = Arc::new(AtomicBool::new(false));
+        tokio::spawn({
+            let mut stop_rx = self.channels.stop.tx.subscribe();
+            let stopped = stopped.clone();
+            let running = self.running.clone();
+            async move {
+                let _ = stop_rx.recv().await;
+                stopped.store(true, Ordering::Relaxed);
+                running.store(false, Ordering::Relaxed);
+            }
+        });
+        tokio::spawn(async move {
+            let mut backoff = MIN_BACKOFF;
+            let mut dropped = false;
+            let mut connected = false; // 首次连接之后的连接计为重连
+            loop {
+                let mut streams = self.streams.read().await.clone();
+                tracing::info!("Wss subscribe, {:?}", streams);
+                if let Some(listen_key) = &self.listen_key {
+                    streams.push(listen_key.read().await.clone());
+                }
+                self.running.store(true, Ordering::Relaxed);
+                // 先置位再检查，避免在两者之间到达的停止通知被覆盖
+                if stopped.load(Ordering::Relaxed) {
+                    tracing::info!("Wss stopped");
+                    return;
+                }
+
+                let started = Instant::now();
+                let reconnect = connected;
+                connected = true;
+                // 事件循环在数据流结束时可能 panic，放到独立任务中运行
+                let handle = tokio::spawn({
+                    let channels = self.channels.clone();
+                    let running = self.running.clone();
+                    async move {
+                        let gap = channels.gap.clone();
+                        let reconnected = channels.reconnected.clone();
+                        let mut wss =
+                            WebSockets::new(move |e: CombinedStreamEvent<WebsocketEventUntag>| {
+                                dispatch(e.data, &channels);
+                                Ok(())
+                            });
+                        wss.connect_multiple(streams).await?;
+                        tracing::info!("Wss connected");
+                        if reconnect {
+                            // 断线重连，或重新订阅、更换 listen key 后的主动重连
+                            let reason = if dropped { "dropped" } else { "forced" };
+                            METRICS.ws_reconnects.with_label_values(&[reason]).inc();
+                            let _ = reconnected.tx.send(());
+                        }
+                        if dropped {
+                            tracing::info!("Wss reconnected, notify data gap");
+                            let _ = gap.tx.send(());
+                        }
+                        wss.event_loop(&running).await
+                    }
+                });
+
+                let forced = match handle.await {
+                    Ok(Ok(())) => {
+                        tracing::info!("Wss reconnecting");
+                        true
+                    }
+                    Ok(Err(e)) => {
+                        tracing::error!("Wss stopped, {:?}", e);
+                        false
+                    }
+                    Err(e) => {
+                        tracing::error!("Wss panicked, {:?}", e);
+                        false
+                    }
+                };
+                dropped = !resubscribing.swap(false, Ordering::Relaxed);
+                if stopped.load(Ordering::Relaxed) {
+                    tracing::info!("Wss stopped");
+                    return;
+                }
+
+                if started.elapsed() >= STABLE_DURATION {
+                    backoff = MIN_BACKOFF;
+                }
+                if !forced {
+                    tracing::info!("Wss reconnect in {:?}", backoff);
+                    time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt;
+
+use binance::rest_model::{Filters, OrderSide, Symbol};
+use serde_json::{json, Value};
+
+/// 订单不满足交易规则且无法修正
+#[derive(Debug, PartialEq)]
+pub enum OrderRejected {
+    /// 价格超出 PRICE_FILTER 范围
+    PriceOutOfRange { price: f64, min: f64, max: f64 },
+    /// 数量超出 LOT_SIZE 范围
+    QuantityOutOfRange { quantity: f64, min: f64, max: f64 },
+    /// 名义价值低于 MIN_NOTIONAL 或 NOTIONAL 的下限
+    BelowMinNotional { notional: f64, min: f64 },
+}
+
+impl fmt::Display for OrderRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderRejected::PriceOutOfRange { price, min, max } => {
+                write!(f, "price {} out of range [{}, {}]", price, min, max)
+            }
+            OrderRejected::QuantityOutOfRange { quantity, min, max } => {
+                write!(f, "quantity {} out of range [{}, {}]", quantity, min, max)
+            }
+            OrderRejected::BelowMinNotional { notional, min } => {
+                write!(f, "notional {} below minimum {}", notional, min)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrderRejected {}
+
+/// 交易对的下单规则，来自交易所的 exchange info。值为 0 表示不限制。
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SymbolFilter {
+    min_price: f64,
+    max_price: f64,
+    tick_size: f64,
+    min_qty: f64,
+    max_qty: f64,
+    step_size: f64,
+    market_min_qty: f64,
+    market_max_qty: f64,
+    market_step_size: f64,
+    min_notional: f64,
+    apply_to_market: bool,
+}
+
+/// binance-rs-async 不认识 NOTIONAL 过滤器，大部分现货交易对已用它取代 MIN_NOTIONAL。
+/// 解析 exchange info 前将其改写为 MIN_NOTIONAL 的格式，只保留下限，上限 maxNotional 不检查。
+pub(crate) fn normalize_notional(info: &mut Value) {
+    let Some(symbols) = info.get_mut("symbols").and_then(Value::as_array_mut) else {
+        return;
+    };
+    let filters = symbols
+        .iter_mut()
+        .filter_map(|symbol| symbol.get_mut("filters").and_then(Value::as_array_mut))
+        .flatten();
+    for filter in filters {
+        if filter["filterType"] != "NOTIONAL" {
+            continue;
+        }
+        *filter = json!({
+            "filterType": "MIN_NOTIONAL",
+            "minNotional": filter["minNotional"],
+            "applyToMarket": filter["applyMinToMarket"],
+            "avgPriceMins": filter["avgPriceMins"],
+        });
+    }
+}
+
+/// 交易对没有最小名义价值的规则时无法判断订单是否会被拒绝，不能交易
+impl TryFrom<&Symbol> for SymbolFilter {
+    type Error = String;
+
+    fn try_from(symbol: &Symbol) -> Result<Self, Self::Error> {
+        let mut filter = SymbolFilter::default();
+        let mut notional = false;
+        for f in &symbol.filters {
+            match *f {
+                Filters::PriceFilter {
+                    min_price,
+                    max_price,
+                    tick_size,
+                } => {
+                    filter.min_price = min_price;
+                    filter.max_price = max_price;
+                    filter.tick_size = tick_size;
+                }
+                Filters::LotSize {
+                    min_qty,
+                    max_qty,
+                    step_size,
+                } => {
+                    filter.min_qty = min_qty;
+                    filter.max_qty = max_qty;
+                    filter.step_size = step_size;
+                }
+                Filters::MarketLotSize {
+                    min_qty,
+                    max_qty,
+                    step_size,
+                } => {
+                    filter.market_min_qty = min_qty;
+                    filter.market_max_qty = max_qty;
+                    filter.market_step_size = step_size;
+                }
+                // MIN_NOTIONAL 与 NOTIONAL 同时存在时取较严的限制
+                Filters::MinNotional {
+                    min_notional,
+                    apply_to_market,
+                    ..
+                } => {
+                    notional = true;
+                    filter.min_notional = filter.min_notional.max(min_notional);
+                    filter.apply_to_market |= apply_to_market;
+                }
+                _ => {}
+            }
+        }
+        if !notional {
+            return Err(format!(
+                "Symbol {} has no MIN_NOTIONAL or NOTIONAL filter",
+                symbol.symbol
+            ));
+        }
+        Ok(filter)
+    }
+}
+
+impl SymbolFilter {
+    /// 修正限价单：买单价格向下、卖单价格向上取整到 tick，数量向下取整到 step。
+    pub(crate) fn limit(
+        &self,
+        side: &OrderSide,
+        price: f64,
+        quantity: f64,
+    ) -> Result<(f64, f64), OrderRejected> {
+        let price = match side {
+            OrderSide::Buy => floor_to(price, self.tick_size),
+            OrderSide::Sell => ceil_to(price, self.tick_size),
+        };
+        check_range(price, self.min_price, self.max_price)
+            .map_err(|(min, max)| OrderRejected::PriceOutOfRange { price, min, max })?;
+
+        let quantity = floor_to(quantity, self.step_size);
+        check_range(quantity, self.min_qty, self.max_qty)
+            .map_err(|(min, max)| OrderRejected::QuantityOutOfRange { quantity, min, max })?;
+
+        self.check_notional(price * quantity)?;
+        Ok((price, quantity))
+    }
+
+    /// 修正市价单数量，`price` 为估算名义价值用的最新价格。
+    pub(crate) fn market(&self, price: f64, quantity: f64) -> Result<f64, OrderRejected> {
+        // MARKET_LOT_SIZE 的 step 为 0 时沿用 LOT_SIZE
+        let step_size = if self.market_step_size > 0. {
+            self.market_step_size
+        } else {
+            self.step_size
+        };
+        let quantity = floor_to(quantity, step_size);
+        check_range(quantity, self.min_qty, self.max_qty)
+            .and_then(|_| check_range(quantity, self.market_min_qty, self.market_max_qty))
+            .map_err(|(min, max)| OrderRejected::QuantityOutOfRange { quantity, min, max })?;
+
+        if self.apply_to_market {
+            self.check_notional(price * quantity)?;
+        }
+        Ok(quantity)
+    }
+
+    fn check_notional(&self, notional: f64) -> Result<(), OrderRejected> {
+        if notional < self.min_notional {
+            return Err(OrderRejected::BelowMinNotional {
+                notional,
+                min: self.min_notional,
+            });
+        }
+        Ok(())
+    }
+}
+
+// 0 表示该边界不限制
+fn check_range(value: f64, min: f64, max: f64) -> Result<(), (f64, f64)> {
+    if value <= 0. || value < min || (max > 0. && value > max) {
+        return Err((min, max));
+    }
+    Ok(())
+}
+
+// 按步长的小数位数四舍五入，消除浮点误差
+fn round_to_step(value: f64, step: f64) -> f64 {
+    let decimals = (-step.log10()).ceil().max(0.) as i32;
+    let scale = 10f64.powi(decimals);
+    (value * scale).round() / scale
+}
+
+fn floor_to(value: f64, step: f64) -> f64 {
+    if step <= 0. {
+        return value;
+    }
+    round_to_step((value / step + 1e-9).floor() * step, step)
+}
+
+fn ceil_to(value: f64, step: f64) -> f64 {
+    if step <= 0. {
+        return value;
+    }
+    round_to_step((value / step - 1e-9).ceil() * step, step)
+}
+
+#[cfg(test)]
+mod tests {
+    use binance::rest_model::{OrderSide, Symbol};
+    use serde_json::json;
+
+    use super::{normalize_notional, OrderRejected, SymbolFilter};
+
+    #[test]
+    fn test_limit() {
+        let filter = SymbolFilter {
+            min_price: 0.01,
+            max_price: 1000000.,
+            tick_size: 0.01,
+            min_qty: 0.00001,
+            max_qty: 9000.,
+            step_size: 0.00001,
+            min_notional: 10.,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            filter.limit(&OrderSide::Buy, 27123.456, 0.000387654),
+            Ok((27123.45, 0.00038))
+        );
+        assert_eq!(
+            filter.limit(&OrderSide::Sell, 27123.451, 0.3),
+            Ok((27123.46, 0.3))
+        );
+        assert!(matches!(
+            filter.limit(&OrderSide::Buy, 27123.45, 0.0003),
+            Err(OrderRejected::BelowMinNotional { .. })
+        ));
+        assert!(matches!(
+            filter.limit(&OrderSide::Buy, 27123.45, 0.000001),
+            Err(OrderRejected::QuantityOutOfRange { .. })
+        ));
+        assert!(matches!(
+            filter.limit(&OrderSide::Buy, 0.001, 1.),
+            Err(OrderRejected::PriceOutOfRange { .. })
+        ));
+
+        assert_eq!(filter.market(27123.45, 0.0012345), Ok(0.00123));
+    }
+
+    #[test]
+    fn test_notional() {
+        let mut info = json!({
+            "symbols": [{
+                "symbol": "BTCUSDT",
+                "filters": [
+                    {"filterType": "PRICE_FILTER", "minPrice": "0.01", "maxPrice": "1000000", "tickSize": "0.01"},
+                    {"filterType": "NOTIONAL", "minNotional": "5.00000000", "applyMinToMarket": true,
+                     "maxNotional": "9000000.00000000", "applyMaxToMarket": false, "avgPriceMins": 5},
+                ],
+            }],
+        });
+        normalize_notional(&mut info);
+        let symbol = |filters| Symbol {
+            symbol: "BTCUSDT".to_string(),
+            status: "TRADING".to_string(),
+            base_asset: "BTC".to_string(),
+            base_asset_precision: 8,
+            quote_asset: "USDT".to_string(),
+            quote_precision: 8,
+            quote_asset_precision: 8,
+            base_commission_precision: 8,
+            quote_commission_precision: 8,
+            order_types: vec![],
+            iceberg_allowed: false,
+            oco_allowed: false,
+            quote_order_qty_market_allowed: false,
+            is_spot_trading_allowed: true,
+            is_margin_trading_allowed: false,
+            filters,
+            permissions: vec![],
+        };
+
+        let filters = serde_json::from_value(info["symbols"][0]["filters"].clone()).unwrap();
+        let filter = SymbolFilter::try_from(&symbol(filters)).unwrap();
+        assert_eq!(filter.min_notional, 5.);
+        assert!(filter.apply_to_market);
+        assert!(matches!(
+            filter.market(27123.45, 0.0001),
+            Err(OrderRejected::BelowMinNotional { .. })
+        ));
+
+        // 没有最小名义价值的规则时拒绝交易，而不是当作不限制
+        assert!(SymbolFilter::try_from(&symbol(vec![])).is_err());
+    }
+}
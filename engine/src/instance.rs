@@ -1,117 +1,2196 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use binance::{
+    account::OrderStatusRequest,
+    rest_model::{KlineSummary, Order as RestOrder, OrderSide, OrderStatus as RestOrderStatus},
+    ws_model::{Kline, KlineEvent},
+};
 use serde::{Deserialize, Serialize};
-use strategies::{Data, DataCategory, DataId, DataIndex, Signal, Strategies, Strategy};
-use tokio::sync::RwLock;
+use strategies::{
+    Category, Data, DataCategory, DataIndex, Evaluation, KlineInterval, Signal, Strategies,
+    Strategy,
+};
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc::UnboundedSender, RwLock},
+    time,
+};
 
 use crate::{
+    action::{ExpectBuy, ExpectSell, Handle, MarketSell, RevokeOrder},
+    capital::Capital,
     channel::{Broadcast, Mpsc},
-    DataChannelIndex,
+    config::{Instance as InstanceConfig, StaleOrder},
+    exchange::{Exchange, Exchanges},
+    filter::SymbolFilter,
+    metrics::METRICS,
+    now,
+    risk::Risk,
+    DataChannelIndex, Fill, InstId, Order, OrderId, OrderStatus, Pnl, Price, Symbol, FEE_RATE,
 };
 
 // 策略生效模式
-#[derive(Default, Clone, Serialize, Deserialize)]
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum StrategyMode {
+    /// 任一策略发出信号即触发
     #[default]
     Or,
-    // And,
-    // Weight,
+    /// 所有策略信号一致才触发
+    And,
+    /// 按策略权重投票，同向权重占比达到阈值才触发
+    Weight,
+}
+
+// 挂单过期后的处理方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaleAction {
+    /// 撤单
+    #[default]
+    Cancel,
+    /// 撤单后按最新报价重新挂单
+    Chase,
+    /// 卖单撤单后市价卖出，买单只撤单
+    Market,
+}
+
+impl StrategyMode {
+    /// 将各策略最近的信号合并为一个交易决策，买卖信号冲突时不操作。
+    ///
+    /// `weights` 与 `signals` 一一对应，`threshold` 仅用于 `Weight` 模式。
+    pub fn decide(&self, signals: &[Signal], weights: &[f64], threshold: f64) -> Signal {
+        match self {
+            StrategyMode::Or => {
+                let buy = signals.contains(&Signal::Buy);
+                let sell = signals.contains(&Signal::Sell);
+                match (buy, sell) {
+                    (true, false) => Signal::Buy,
+                    (false, true) => Signal::Sell,
+                    _ => Signal::Nothing,
+                }
+            }
+            StrategyMode::And => match signals.first() {
+                Some(first) if signals.iter().all(|s| s == first) => *first,
+                _ => Signal::Nothing,
+            },
+            StrategyMode::Weight => {
+                let total = weights.iter().sum::<f64>();
+                if total <= 0. {
+                    return Signal::Nothing;
+                }
+                let (buy, sell) =
+                    signals
+                        .iter()
+                        .zip(weights)
+                        .fold((0., 0.), |(buy, sell), (signal, weight)| match signal {
+                            Signal::Buy => (buy + weight, sell),
+                            Signal::Sell => (buy, sell + weight),
+                            Signal::Nothing => (buy, sell),
+                        });
+                if buy > sell && buy / total >= threshold {
+                    Signal::Buy
+                } else if sell > buy && sell / total >= threshold {
+                    Signal::Sell
+                } else {
+                    Signal::Nothing
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct StrategySignal {
-    id: u64,
-    symbol: String,
+    index: usize, // 策略在实例中的序号
     signal: Signal,
+    price: f64, // 产生信号时的价格
 }
 
+/// 实例的交易决策
+#[derive(Debug)]
+pub struct Decision {
+    pub(crate) inst_id: InstId,
+    pub(crate) signal: Signal,
+    pub(crate) price: f64,
+    pub(crate) strategies: Vec<String>, // 给出同向信号的策略
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum State {
     WaitBuy,
     WaitSell,
 }
 
-type EventTime = u64;
+/// 买单成交后的持仓
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Position {
+    pub(crate) quantity: f64,
+    pub(crate) buy_price: f64,
+    pub(crate) min_sell_price: f64, // 保本价
+    pub(crate) cost: f64,           // 买入占用的资金
+    #[serde(default)]
+    pub(crate) fee: f64, // 未从持仓数量中扣除的买入手续费，折合计价资产
+}
+
+/// 需要持久化的实例状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct InstanceSnapshot {
+    pub(crate) id: InstId,
+    pub(crate) state: State,
+    pub(crate) order_id: Option<OrderId>,
+    pub(crate) position: Option<Position>,
+    pub(crate) sell_pending: Option<f64>,
+    pub(crate) stopping: bool,
+    #[serde(default)]
+    pub(crate) seq: u64,
+    #[serde(default)]
+    pub(crate) client_order_id: Option<String>,
+    #[serde(default)]
+    pub(crate) chases: u32,
+    #[serde(default)]
+    pub(crate) paused: bool,
+    #[serde(default)]
+    pub(crate) pnl: Pnl,
+}
 
 /// 一个实例只能拥有一个订单
 pub struct Instance {
-    pub(crate) symbol: String,                           // 交易对名称
-    pub(crate) strategies: Vec<Arc<RwLock<Strategies>>>, // 策略
-    pub(crate) strategy_mode: StrategyMode,              // 策略模式
-    pub(crate) signal_channel: Mpsc<StrategySignal>,     // 接收来自策略的交易信号
+    pub(crate) id: InstId,                                         // 实例ID
+    pub(crate) symbol: String,                                     // 交易对名称
+    pub(crate) strategies: Vec<Arc<RwLock<Strategies>>>,           // 策略
+    pub(crate) strategy_ids: Vec<String>, // 策略ID，未配置时取策略名称，用于交易日志
+    pub(crate) weights: Vec<f64>,         // 策略权重
+    pub(crate) strategy_mode: StrategyMode, // 策略模式
+    pub(crate) threshold: f64,            // 权重模式的触发阈值
+    pub(crate) signal_channel: Mpsc<StrategySignal>, // 接收来自策略的交易信号
     pub(crate) state: Arc<RwLock<State>>, // 实例状态，初始为wait buy，挂买单转wait sell，买单成功后挂卖单，卖单完成后转wait buy。
-    pub(crate) current_signals: Arc<RwLock<HashMap<EventTime, Vec<StrategySignal>>>>, // 缓存信号
+    pub(crate) current_signals: Arc<RwLock<Vec<Signal>>>, // 每个策略最近一次的信号
+    pub(crate) capital: Arc<RwLock<Capital>>, // 资金分配
+    pub(crate) order_id: Option<OrderId>, // 当前挂单
+    pub(crate) revoked: HashSet<OrderId>, // 已撤销、等待结算成交部分的订单
+    pub(crate) revoke_retry: Option<Instant>, // 撤单失败后查询订单状态的时间
+    pub(crate) order_done: Option<UnboundedSender<OrderId>>, // 通知引擎按订单完成结算
+    pub(crate) position: Option<Position>, // 持仓
+    pub(crate) sell_pending: Option<f64>, // 买单成交前收到的卖出信号价格，成交后立即挂卖单
+    pub(crate) stop_loss: f64,            // 止损下跌幅度
+    pub(crate) stopping: bool,            // 已触发止损，等待市价卖单成交
+    pub(crate) seq: u64,                  // 客户端订单ID的序号
+    pub(crate) client_order_id: Option<String>, // 最近一次提交的客户端订单ID
+    pub(crate) filter: SymbolFilter,      // 交易对的下单规则
+    pub(crate) optimal_price: Arc<RwLock<HashMap<Symbol, Price>>>, // 各交易对的最优报价
+    pub(crate) price_offset: f64,         // 限价单相对最优价的偏移比例
+    pub(crate) stale_order: StaleOrder,   // 挂单过期的处理策略
+    pub(crate) chases: u32,               // 当前挂单已追价的次数
+    pub(crate) risk: Arc<RwLock<Risk>>,   // 全局风控
+    pub(crate) paused: bool,              // 已暂停，不再处理交易决策，止损与订单回报照常处理
+    pub(crate) pnl: Pnl,                  // 盈亏
+    pub(crate) fired: Vec<String>,        // 触发最近一次交易决策的策略，记录到订单
+    pub(crate) stop: Broadcast<()>,       // 停止策略与信号处理任务
 }
 
 impl Instance {
-    pub fn new(symbol: &str, mode: StrategyMode, strategies: Vec<Strategies>) -> Self {
+    pub fn new(
+        id: &str,
+        symbol: &str,
+        mode: StrategyMode,
+        threshold: f64,
+        stop_loss: f64,
+        capital: Arc<RwLock<Capital>>,
+        strategies: Vec<(Strategies, f64)>,
+    ) -> Self {
+        let strategy_ids = strategies.iter().map(|(s, _)| strategy_label(s)).collect();
+        let (strategies, weights): (Vec<_>, Vec<_>) = strategies
+            .into_iter()
+            .map(|(strategy, weight)| (Arc::new(RwLock::new(strategy)), weight))
+            .unzip();
         Self {
+            id: id.to_string(),
             symbol: symbol.to_string(),
-            strategies: strategies
-                .into_iter()
-                .map(|e| Arc::new(RwLock::new(e)))
-                .collect::<Vec<_>>(),
+            current_signals: Arc::new(RwLock::new(vec![Signal::Nothing; strategies.len()])),
+            strategies,
+            strategy_ids,
+            weights,
             strategy_mode: mode,
+            threshold,
             signal_channel: Default::default(),
             state: Arc::new(RwLock::new(State::WaitBuy)),
-            current_signals: Default::default(),
+            capital,
+            order_id: None,
+            revoked: HashSet::new(),
+            revoke_retry: None,
+            order_done: None,
+            position: None,
+            sell_pending: None,
+            stop_loss,
+            stopping: false,
+            seq: 0,
+            client_order_id: None,
+            filter: SymbolFilter::default(),
+            optimal_price: Default::default(),
+            price_offset: 0.,
+            stale_order: StaleOrder::default(),
+            chases: 0,
+            risk: Default::default(),
+            paused: false,
+            pnl: Pnl::default(),
+            fired: Vec::new(),
+            stop: Default::default(),
+        }
+    }
+
+    /// 使用配置的策略ID，为空的保留策略名称
+    pub(crate) fn with_strategy_ids(mut self, ids: Vec<String>) -> Self {
+        for (strategy_id, id) in self.strategy_ids.iter_mut().zip(ids) {
+            if !id.is_empty() {
+                *strategy_id = id;
+            }
+        }
+        self
+    }
+
+    pub(crate) fn with_risk(mut self, risk: Arc<RwLock<Risk>>) -> Self {
+        self.risk = risk;
+        self
+    }
+
+    pub(crate) fn with_order_done(mut self, order_done: UnboundedSender<OrderId>) -> Self {
+        self.order_done = Some(order_done);
+        self
+    }
+
+    pub fn with_stale_order(mut self, stale_order: StaleOrder) -> Self {
+        self.stale_order = stale_order;
+        self
+    }
+
+    /// 使用 book ticker 维护的最优报价为限价单定价
+    pub fn with_quotes(
+        mut self,
+        optimal_price: Arc<RwLock<HashMap<Symbol, Price>>>,
+        price_offset: f64,
+    ) -> Self {
+        self.optimal_price = optimal_price;
+        self.price_offset = price_offset;
+        self
+    }
+
+    /// 按最新价格计算持仓的浮动盈亏，扣除买入手续费
+    pub(crate) fn mark(&mut self, price: f64) -> f64 {
+        self.pnl.unrealized = self
+            .position
+            .as_ref()
+            .map_or(0., |p| price * p.quantity - p.cost - p.fee);
+        self.pnl.unrealized
+    }
+
+    /// 限价单价格：买单取买一价、卖单取卖一价并按偏移调整，尚无报价时使用信号价格。
+    pub(crate) async fn limit_price(&self, side: &OrderSide, signal_price: f64) -> f64 {
+        let Some(price) = self.optimal_price.read().await.get(&self.symbol).copied() else {
+            tracing::info!(
+                "Instance {} has no book ticker yet, use signal price {}",
+                self.id,
+                signal_price
+            );
+            return signal_price;
+        };
+        match side {
+            OrderSide::Buy => price.buy * (1. - self.price_offset),
+            OrderSide::Sell => price.sell * (1. + self.price_offset),
         }
     }
 
-    pub async fn run(&mut self, data_channels: &HashMap<DataChannelIndex, Broadcast<Data>>) {
-        self.run_strategies(data_channels).await;
+    pub async fn run(
+        &mut self,
+        exchange: &Arc<Exchanges>,
+        data_channels: &HashMap<DataChannelIndex, Broadcast<Data>>,
+        data_gap: &Broadcast<()>,
+        decision_tx: UnboundedSender<Decision>,
+    ) {
+        self.run_strategies(exchange, data_channels, data_gap).await;
         // 处理交易信号
-        let mut signal_rx = unsafe { self.signal_channel.rx.take().unwrap_unchecked() };
+        let mut signal_rx = self
+            .signal_channel
+            .rx
+            .take()
+            .expect("signal receiver already taken");
+        let mut stop_rx = self.stop.tx.subscribe();
         let current_signals = self.current_signals.clone();
         tokio::spawn({
+            let id = self.id.clone();
+            let mode = self.strategy_mode.clone();
+            let weights = self.weights.clone();
+            let strategy_ids = self.strategy_ids.clone();
+            let threshold = self.threshold;
             async move {
                 loop {
-                    if let Some(signal) = signal_rx.recv().await {
+                    let signal = tokio::select! {
+                        signal = signal_rx.recv() => signal,
+                        _ = stop_rx.recv() => break,
+                    };
+                    if let Some(signal) = signal {
                         tracing::info!("Instance handle signal{:?}", signal);
-                        let mut current_signals = current_signals.write().await;
-                        if let Some(signals) = current_signals.get_mut(&signal.id) {
-                            signals.push(signal);
-                        }
+                        let (decision, strategies) = {
+                            let mut current_signals = current_signals.write().await;
+                            current_signals[signal.index] = signal.signal;
+                            let decision = mode.decide(&current_signals, &weights, threshold);
+                            let strategies = current_signals
+                                .iter()
+                                .zip(&strategy_ids)
+                                .filter(|(s, _)| **s == decision)
+                                .map(|(_, id)| id.clone())
+                                .collect::<Vec<_>>();
+                            (decision, strategies)
+                        };
 
-                        // 检查每一轮
+                        if decision != Signal::Nothing {
+                            let decision = Decision {
+                                inst_id: id.clone(),
+                                signal: decision,
+                                price: signal.price,
+                                strategies,
+                            };
+                            if let Err(e) = decision_tx.send(decision) {
+                                tracing::error!("Send decision failed, {:?}", e);
+                            }
+                        }
                     }
                 }
             }
         });
     }
 
-    pub async fn run_strategies(&self, data_channels: &HashMap<DataChannelIndex, Broadcast<Data>>) {
-        for strategy in self.strategies.iter().map(Clone::clone) {
+    pub async fn run_strategies(
+        &self,
+        exchange: &Arc<Exchanges>,
+        data_channels: &HashMap<DataChannelIndex, Broadcast<Data>>,
+        data_gap: &Broadcast<()>,
+    ) {
+        for (index, strategy) in self.strategies.iter().cloned().enumerate() {
             let signal_tx = self.signal_channel.tx.clone();
-            let data_category = { strategy.read().await.data_category() };
+            let (strategy_name, data_category) = {
+                let strategy = strategy.read().await;
+                (strategy_label(&strategy), strategy.data_category())
+            };
 
             let mut data_rx = data_channels
                 .get(&(self.symbol.clone(), data_category).data_index())
                 .unwrap()
                 .tx
                 .subscribe();
+            let mut gap_rx = data_gap.tx.subscribe();
+            let mut stop_rx = self.stop.tx.subscribe();
 
             tokio::spawn({
+                let id = self.id.clone();
                 let symbol = self.symbol.clone();
+                let exchange = exchange.clone();
                 async move {
                     loop {
-                        match data_rx.recv().await {
+                        // 先处理断线通知，补齐缺失的K线后再接收重连后的数据
+                        let data = tokio::select! {
+                            biased;
+                            Ok(()) = gap_rx.recv() => {
+                                match warm_up_strategy(&exchange, &symbol, &strategy).await {
+                                    Ok(fed) => tracing::info!(
+                                        "Instance {} backfilled {} {} klines",
+                                        id,
+                                        fed,
+                                        strategy_name
+                                    ),
+                                    Err(e) => tracing::error!(
+                                        "Backfill instance {} {} failed, {:?}",
+                                        id,
+                                        strategy_name,
+                                        e
+                                    ),
+                                }
+                                continue;
+                            }
+                            data = data_rx.recv() => data,
+                            _ = stop_rx.recv() => break,
+                        };
+                        match data {
                             Ok(data) => {
-                                let data_id = data.data_id(); // 数据ID用于确认是否为同一刻数据。
-                                let signal = { strategy.write().await.signal(data) };
+                                let mut strategy = strategy.write().await;
+                                // 收盘模式下未收盘的K线不产生信号，避免覆盖已有信号
+                                if let Data::Kline(k) = &data {
+                                    if !k.kline.is_final_bar
+                                        && strategy.evaluation() == Evaluation::Close
+                                    {
+                                        continue;
+                                    }
+                                }
+                                let price = match &data {
+                                    Data::Kline(k) => k.kline.close,
+                                    Data::BookTicker(b) => (b.best_bid + b.best_ask) / 2.,
+                                };
+                                let signal = strategy.signal(data);
+                                drop(strategy);
+                                METRICS
+                                    .signals
+                                    .with_label_values(&[
+                                        id.as_str(),
+                                        strategy_name.as_str(),
+                                        &format!("{:?}", signal).to_lowercase(),
+                                    ])
+                                    .inc();
                                 let _ = signal_tx.send(StrategySignal {
-                                    id: data_id,
-                                    symbol: symbol.clone(),
+                                    index,
                                     signal,
+                                    price,
                                 });
                             }
-                            Err(err) => {
-                                tracing::info!("Recv Data failed, {:?}", err);
+                            Err(RecvError::Lagged(n)) => {
+                                tracing::info!("Recv Data lagged, skipped {}", n);
+                                METRICS
+                                    .broadcast_lagged
+                                    .with_label_values(&["strategy"])
+                                    .inc_by(n);
                             }
+                            Err(RecvError::Closed) => break,
                         }
                     }
                 }
             });
         }
     }
+
+    /// 停止策略与信号处理任务，之后可以重新运行。
+    pub(crate) fn stop(&mut self) {
+        let _ = self.stop.tx.send(());
+        self.stop = Default::default();
+        self.signal_channel = Default::default();
+    }
+
+    /// 应用新的实例配置，调用前需停止实例任务。K线周期与计算周期不变的策略保留数据只更新参数，
+    /// 其余策略重建，返回需要预热的新策略。
+    pub(crate) async fn reconfigure(
+        &mut self,
+        conf: &InstanceConfig,
+        old: &InstanceConfig,
+    ) -> Vec<Arc<RwLock<Strategies>>> {
+        self.strategy_mode = conf.mode.clone();
+        self.threshold = conf.threshold;
+        self.stop_loss = conf.stop_loss;
+        self.price_offset = conf.price_offset;
+        self.stale_order = conf.stale_order.clone();
+
+        let current_signals = self.current_signals.read().await.clone();
+        let mut strategies = Vec::new();
+        let mut signals = Vec::new();
+        let mut fresh = Vec::new();
+        let mut strategy_ids = Vec::new();
+        for strategy_conf in &conf.strategies {
+            let built = strategy_conf.build();
+            strategy_ids.push(match strategy_conf.id() {
+                "" => strategy_label(&built),
+                id => id.to_string(),
+            });
+            let kept = old
+                .strategies
+                .iter()
+                .position(|s| !s.id().is_empty() && s.id() == strategy_conf.id());
+            let mut updated = false;
+            if let Some(i) = kept {
+                if self.strategies[i].write().await.update(&built) {
+                    strategies.push(self.strategies[i].clone());
+                    signals.push(current_signals[i]);
+                    updated = true;
+                }
+            }
+            if !updated {
+                let strategy = Arc::new(RwLock::new(built));
+                fresh.push(strategy.clone());
+                strategies.push(strategy);
+                signals.push(Signal::Nothing);
+            }
+        }
+        tracing::info!(
+            "Instance {} reconfigured, {} of {} strategies rebuilt",
+            self.id,
+            fresh.len(),
+            strategies.len()
+        );
+        self.strategies = strategies;
+        self.strategy_ids = strategy_ids;
+        self.weights = conf.strategies.iter().map(|s| s.weight()).collect();
+        self.current_signals = Arc::new(RwLock::new(signals));
+        fresh
+    }
+
+    /// 用历史K线预热全部策略
+    pub(crate) async fn warm_up(&self, exchange: &Exchanges) -> binance::errors::Result<()> {
+        self.warm_up_strategies(exchange, &self.strategies).await
+    }
+
+    /// 用历史K线预热策略缓冲，产生的信号直接丢弃，不会下单。
+    pub(crate) async fn warm_up_strategies(
+        &self,
+        exchange: &Exchanges,
+        strategies: &[Arc<RwLock<Strategies>>],
+    ) -> binance::errors::Result<()> {
+        for strategy in strategies {
+            let fed = warm_up_strategy(exchange, &self.symbol, strategy).await?;
+            if fed > 0 {
+                let label = strategy_label(&*strategy.read().await);
+                tracing::info!("Instance {} warmed up {} {} klines", self.id, fed, label);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn snapshot(&self) -> InstanceSnapshot {
+        InstanceSnapshot {
+            id: self.id.clone(),
+            state: *self.state.read().await,
+            order_id: self.order_id.clone(),
+            position: self.position.clone(),
+            sell_pending: self.sell_pending,
+            stopping: self.stopping,
+            seq: self.seq,
+            client_order_id: self.client_order_id.clone(),
+            chases: self.chases,
+            paused: self.paused,
+            pnl: self.pnl,
+        }
+    }
+
+    /// 从状态日志恢复，资金占用在对账后统一恢复。
+    pub(crate) async fn restore(&mut self, snapshot: InstanceSnapshot) {
+        *self.state.write().await = snapshot.state;
+        self.order_id = snapshot.order_id;
+        self.position = snapshot.position;
+        self.sell_pending = snapshot.sell_pending;
+        self.stopping = snapshot.stopping;
+        self.seq = snapshot.seq;
+        self.client_order_id = snapshot.client_order_id;
+        self.chases = snapshot.chases;
+        self.paused = snapshot.paused;
+        self.pnl = snapshot.pnl;
+    }
+
+    /// 客户端订单ID的前缀，标识订单所属实例。
+    pub(crate) fn client_order_prefix(&self) -> String {
+        client_order_prefix(&self.id)
+    }
+
+    /// 生成下一个客户端订单ID：`bq{实例ID}-{序号}`，序号随状态持久化并在对账时与交易所同步。
+    fn next_client_order_id(&mut self) -> String {
+        self.seq += 1;
+        let client_order_id = format!("{}{}", self.client_order_prefix(), self.seq);
+        self.client_order_id = Some(client_order_id.clone());
+        client_order_id
+    }
+
+    /// 登记新提交的订单作为当前挂单。下单期间不持有订单表的锁，订单回报可能已按客户端订单ID
+    /// 先行登记，此时保留回报中的状态与成交，只补充实例侧的信息。
+    async fn register(&mut self, orders: &RwLock<HashMap<OrderId, Order>>, order: Order) {
+        let order_id = order.id.to_string();
+        match orders.write().await.entry(order_id.clone()) {
+            Entry::Occupied(mut e) => {
+                let o = e.get_mut();
+                o.strategies = order.strategies;
+                o.quality = order.quality;
+                o.price = order.price;
+                o.buy_price = order.buy_price;
+                o.min_sell_price = order.min_sell_price;
+            }
+            Entry::Vacant(e) => {
+                e.insert(order);
+            }
+        }
+        self.order_id = Some(order_id);
+    }
+
+    /// 下单。网络等错误时请求是否到达交易所未知，间隔一段时间后按客户端订单ID查询：查到订单直接返回，
+    /// 交易所确认订单不存在才以相同的客户端订单ID重新下单，查询失败则继续查询。
+    /// 交易所只在原订单仍挂着时拒绝重复的客户端订单ID，已成交的订单或市价单再次提交会重复下单。
+    async fn submit<A: Handle + Sync>(
+        &self,
+        exchange: &Exchanges,
+        action: &A,
+        client_order_id: &str,
+    ) -> binance::errors::Result<(u64, u64)> {
+        {
+            let capital = self.capital.read().await;
+            let mut risk = self.risk.write().await;
+            if let Err(e) = risk.check(&self.id, &action.side(), &capital, now()) {
+                METRICS
+                    .orders
+                    .with_label_values(&[self.id.as_str(), "risk_rejected"])
+                    .inc();
+                return Err(binance::errors::Error::Msg(format!("Risk rejected, {}", e)));
+            }
+        }
+        let mut attempt = 0;
+        loop {
+            let started = Instant::now();
+            let result = action.handle(exchange).await;
+            METRICS
+                .order_latency
+                .with_label_values(&[self.id.as_str()])
+                .observe(started.elapsed().as_secs_f64());
+            let mut err = match result {
+                Ok(resp) => {
+                    METRICS
+                        .orders
+                        .with_label_values(&[self.id.as_str(), "placed"])
+                        .inc();
+                    return Ok((resp.order_id, resp.transact_time));
+                }
+                Err(e) => e,
+            };
+            loop {
+                attempt += 1;
+                if !is_retryable(&err) || attempt >= PLACE_ATTEMPTS {
+                    METRICS
+                        .orders
+                        .with_label_values(&[self.id.as_str(), "rejected"])
+                        .inc();
+                    return Err(err);
+                }
+                tracing::error!(
+                    "Instance {} place order {} failed, check status {}, {:?}",
+                    self.id,
+                    client_order_id,
+                    attempt,
+                    err
+                );
+                time::sleep(PLACE_BACKOFF * attempt as u32).await;
+
+                let request = OrderStatusRequest {
+                    symbol: self.symbol.clone(),
+                    orig_client_order_id: Some(client_order_id.to_string()),
+                    ..Default::default()
+                };
+                match exchange.order_status(request).await {
+                    Ok(order) => {
+                        METRICS
+                            .orders
+                            .with_label_values(&[self.id.as_str(), "placed"])
+                            .inc();
+                        return Ok((order.order_id, order.update_time));
+                    }
+                    Err(e) if is_order_missing(&e) => break,
+                    Err(e) => err = e,
+                }
+            }
+        }
+    }
+
+    /// 启动时根据交易所的订单恢复实例状态：最近一笔买单成交且未卖出则持仓，仍在挂的订单登记到引擎。
+    pub(crate) async fn reconcile(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) -> binance::errors::Result<()> {
+        let prefix = self.client_order_prefix();
+        let mut history = exchange.all_orders(&self.symbol).await?;
+        // 挂单可能早于最近订单的查询范围
+        for order in exchange.open_orders(&self.symbol).await? {
+            if !history.iter().any(|o| o.order_id == order.order_id) {
+                history.push(order);
+            }
+        }
+        history.retain(|o| o.client_order_id.starts_with(&prefix));
+        history.sort_by_key(|o| o.time);
+
+        // 序号从交易所已有的最大序号之后继续，避免ID重复
+        let max_seq = history
+            .iter()
+            .filter_map(|o| o.client_order_id[prefix.len()..].parse::<u64>().ok())
+            .max()
+            .unwrap_or_default();
+        self.seq = self.seq.max(max_seq);
+
+        // 交易所查不到该实例的订单时，沿用状态日志中恢复的状态
+        if !history.is_empty() {
+            self.rebuild(&history, orders).await;
+        }
+
+        // 恢复资金占用
+        let open_buy = match &self.order_id {
+            Some(order_id) => orders
+                .read()
+                .await
+                .get(order_id)
+                .filter(|o| o.side == OrderSide::Buy)
+                .map(|o| o.quality * o.price),
+            None => None,
+        };
+        let used = match (&self.position, open_buy) {
+            (Some(position), _) => position.cost,
+            (None, Some(amount)) => amount,
+            _ => 0.,
+        };
+        if used > 0. {
+            if let Err(e) = self.capital.write().await.lock(&self.id, used) {
+                tracing::error!("Instance {} restore capital failed, {}", self.id, e);
+            }
+        }
+
+        let state = if self.position.is_some() || self.order_id.is_some() {
+            State::WaitSell
+        } else {
+            State::WaitBuy
+        };
+        tracing::info!(
+            "Instance {} reconciled, state: {:?}, order: {:?}, position: {:?}",
+            self.id,
+            state,
+            self.order_id,
+            self.position
+        );
+        *self.state.write().await = state;
+        Ok(())
+    }
+
+    // 按交易所的订单历史重建持仓与挂单，历史需按时间排序
+    async fn rebuild(&mut self, history: &[RestOrder], orders: &RwLock<HashMap<OrderId, Order>>) {
+        // 最近一笔已结束且有成交的买单，包括部分成交后撤销的买单
+        let last_buy = history.iter().rposition(|o| {
+            o.side == OrderSide::Buy
+                && o.executed_qty > 0.
+                && !OrderStatus::from(&o.status).is_open()
+        });
+        let rebuilt = last_buy
+            .filter(|&i| {
+                !history[i + 1..]
+                    .iter()
+                    .any(|o| o.side == OrderSide::Sell && o.status == RestOrderStatus::Filled)
+            })
+            .map(|i| rebuild_position(&history[i], &history[i + 1..]));
+        // 状态日志中的持仓有准确的数量与手续费，与交易所历史一致时沿用
+        self.position = match (self.position.take(), rebuilt) {
+            (Some(journaled), Some((rebuilt, tolerance)))
+                if (journaled.buy_price - rebuilt.buy_price).abs() <= rebuilt.buy_price * 1e-9
+                    && (journaled.quantity - rebuilt.quantity).abs() <= tolerance =>
+            {
+                Some(journaled)
+            }
+            (journaled, rebuilt) => {
+                if journaled.is_some() {
+                    tracing::error!(
+                        "Instance {} journaled position {:?} differs from the exchange, rebuilt",
+                        self.id,
+                        journaled
+                    );
+                }
+                rebuilt.map(|(position, _)| position)
+            }
+        };
+
+        let mut orders = orders.write().await;
+        // 同步状态日志中订单的最新状态
+        for o in history {
+            if let Some(order) = orders.get_mut(&o.order_id.to_string()) {
+                order.status = OrderStatus::from(&o.status);
+                order.filled.quantity = o.executed_qty;
+                if o.executed_qty > 0. {
+                    order.filled.avg_price = o.cummulative_quote_qty / o.executed_qty;
+                }
+                order.update_ts = o.update_time;
+            }
+        }
+
+        self.order_id = None;
+        let open = history
+            .iter()
+            .rev()
+            .find(|o| OrderStatus::from(&o.status).is_open());
+        if let Some(open) = open {
+            let buy_price = match &self.position {
+                Some(position) => position.buy_price,
+                None => open.price,
+            };
+            orders.insert(
+                open.order_id.to_string(),
+                Order {
+                    inst_id: self.id.clone(),
+                    symbol: self.symbol.clone(),
+                    id: open.order_id,
+                    client_order_id: open.client_order_id.clone(),
+                    strategies: Vec::new(),
+                    side: open.side.clone(),
+                    quality: open.orig_qty,
+                    price: open.price,
+                    buy_price,
+                    min_sell_price: min_sell_price(buy_price),
+                    status: OrderStatus::from(&open.status),
+                    filled: Fill {
+                        quantity: open.executed_qty,
+                        avg_price: if open.executed_qty > 0. {
+                            open.cummulative_quote_qty / open.executed_qty
+                        } else {
+                            0.
+                        },
+                        ..Default::default()
+                    },
+                    update_ts: open.update_time,
+                },
+            );
+            self.order_id = Some(open.order_id.to_string());
+        }
+    }
+
+    /// WaitBuy 时挂限价买单，挂单成功后转 WaitSell。
+    pub(crate) async fn buy(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+        price: f64,
+    ) {
+        // 用实例的全部可用资金下单，按交易规则修正后占用资金，超出预算的订单不会发出
+        let available = { self.capital.read().await.available(&self.id) };
+        if available <= 0. {
+            tracing::info!("Instance {} has no available capital", self.id);
+            return;
+        }
+        let price = self.limit_price(&OrderSide::Buy, price).await;
+        let (price, quantity) = match self.filter.limit(&OrderSide::Buy, price, available / price) {
+            Ok(order) => order,
+            Err(e) => {
+                tracing::error!("Instance {} buy order rejected, {}", self.id, e);
+                return;
+            }
+        };
+        let amount = price * quantity;
+        if let Err(e) = self.capital.write().await.lock(&self.id, amount) {
+            tracing::error!("Instance {} lock capital failed, {}", self.id, e);
+            return;
+        }
+
+        let client_order_id = self.next_client_order_id();
+        let action = ExpectBuy {
+            symbol: self.symbol.clone(),
+            price,
+            quantity,
+            client_order_id: client_order_id.clone(),
+        };
+        // 下单期间不持有订单表的锁，先到的订单回报按客户端订单ID路由登记
+        match self.submit(exchange, &action, &client_order_id).await {
+            Ok((id, ts)) => {
+                tracing::info!("Instance {} placed buy order {}", self.id, id);
+                self.register(
+                    orders,
+                    Order {
+                        inst_id: self.id.clone(),
+                        symbol: self.symbol.clone(),
+                        id,
+                        client_order_id,
+                        strategies: self.fired.clone(),
+                        side: OrderSide::Buy,
+                        quality: action.quantity,
+                        price,
+                        buy_price: price,
+                        min_sell_price: min_sell_price(price),
+                        status: OrderStatus::Committed,
+                        filled: Fill::default(),
+                        update_ts: ts,
+                    },
+                )
+                .await;
+                *self.state.write().await = State::WaitSell;
+            }
+            Err(e) => {
+                tracing::error!("Instance {} place buy order failed, {:?}", self.id, e);
+                self.capital.write().await.release(&self.id, amount);
+            }
+        }
+    }
+
+    /// 处理交易决策：WaitBuy 时买入，WaitSell 时卖出。暂停时不处理，风控停止交易后只处理卖出，
+    /// 返回是否已下单。
+    pub(crate) async fn on_decision(
+        &mut self,
+        decision: Decision,
+        halted: bool,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) -> bool {
+        if self.paused {
+            tracing::info!("Instance {} paused, skip decision", self.id);
+            return false;
+        }
+        if halted && decision.signal != Signal::Sell {
+            tracing::info!("Instance {} trading halted, skip decision", self.id);
+            return false;
+        }
+        let state = { *self.state.read().await };
+        self.fired = decision.strategies;
+        match (state, decision.signal) {
+            (State::WaitBuy, Signal::Buy) if self.order_id.is_none() => {
+                self.buy(exchange, orders, decision.price).await;
+            }
+            (State::WaitSell, Signal::Sell) => {
+                self.sell(exchange, orders, decision.price).await;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// WaitSell 时按持仓挂限价卖单，价格不低于保本价。买单尚未成交时，成交后再挂卖单。
+    pub(crate) async fn sell(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+        price: f64,
+    ) {
+        let Some(position) = self.position.clone() else {
+            self.sell_pending = Some(price);
+            return;
+        };
+        if self.order_id.is_some() {
+            return;
+        }
+
+        let price = self.limit_price(&OrderSide::Sell, price).await;
+        let (price, quantity) = match self.filter.limit(
+            &OrderSide::Sell,
+            price.max(position.min_sell_price),
+            position.quantity,
+        ) {
+            Ok(order) => order,
+            Err(e) => {
+                tracing::error!("Instance {} sell order rejected, {}", self.id, e);
+                return;
+            }
+        };
+        let client_order_id = self.next_client_order_id();
+        let action = ExpectSell {
+            symbol: self.symbol.clone(),
+            price,
+            quantity,
+            client_order_id: client_order_id.clone(),
+        };
+        match self.submit(exchange, &action, &client_order_id).await {
+            Ok((id, ts)) => {
+                tracing::info!("Instance {} placed sell order {}", self.id, id);
+                self.register(
+                    orders,
+                    Order {
+                        inst_id: self.id.clone(),
+                        symbol: self.symbol.clone(),
+                        id,
+                        client_order_id,
+                        strategies: self.fired.clone(),
+                        side: OrderSide::Sell,
+                        quality: action.quantity,
+                        price: action.price,
+                        buy_price: position.buy_price,
+                        min_sell_price: position.min_sell_price,
+                        status: OrderStatus::Committed,
+                        filled: Fill::default(),
+                        update_ts: ts,
+                    },
+                )
+                .await;
+            }
+            Err(e) => {
+                tracing::error!("Instance {} place sell order failed, {:?}", self.id, e);
+            }
+        }
+    }
+
+    /// 持仓期间价格跌破止损线时，撤销挂着的卖单并市价卖出。
+    pub(crate) async fn check_stop_loss(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+        price: f64,
+    ) {
+        // 已撤销的订单结算前持仓未定，等待结算
+        if self.stopping || self.stop_loss <= 0. || !self.revoked.is_empty() {
+            return;
+        }
+        let Some(position) = self.position.clone() else {
+            return;
+        };
+        let stop_price = position.buy_price * (1. - self.stop_loss);
+        if price > stop_price {
+            return;
+        }
+        tracing::info!(
+            "Instance {} stop loss triggered, price: {}, stop price: {}",
+            self.id,
+            price,
+            stop_price
+        );
+
+        // 卖单已部分成交时只卖出剩余的持仓，剩余数量不满足交易规则时不撤单
+        let order_id = self.order_id.clone();
+        let sold = self.sold_quantity(orders, order_id.as_ref()).await;
+        if let Err(e) = self.filter.market(price, position.quantity - sold) {
+            tracing::error!("Instance {} stop loss order rejected, {}", self.id, e);
+            return;
+        }
+
+        if !self.revoke(exchange, orders).await {
+            return;
+        }
+        self.sell_pending = None;
+        // 撤单前可能又有成交，按撤单后查询到的最终成交计算剩余持仓
+        let sold = self.sold_quantity(orders, order_id.as_ref()).await;
+        let quantity = match self.filter.market(price, position.quantity - sold) {
+            Ok(quantity) => quantity,
+            Err(e) => {
+                tracing::error!("Instance {} stop loss order rejected, {}", self.id, e);
+                return;
+            }
+        };
+        if self
+            .market_sell(exchange, orders, &position, quantity, price)
+            .await
+        {
+            self.stopping = true;
+        }
+    }
+
+    // 当前卖单的成交数量，不是卖单时为 0
+    async fn sold_quantity(
+        &self,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+        order_id: Option<&OrderId>,
+    ) -> f64 {
+        let Some(order_id) = order_id else {
+            return 0.;
+        };
+        orders
+            .read()
+            .await
+            .get(order_id)
+            .filter(|o| o.side == OrderSide::Sell)
+            .map(|o| o.filled.quantity)
+            .unwrap_or_default()
+    }
+
+    // 已撤销订单的最终成交数量
+    async fn revoked_filled(&self, orders: &RwLock<HashMap<OrderId, Order>>, order: &Order) -> f64 {
+        orders
+            .read()
+            .await
+            .get(&order.id.to_string())
+            .map(|o| o.filled.quantity)
+            .unwrap_or_default()
+    }
+
+    /// 撤销当前挂单，返回挂单是否已撤销且最终成交已确定。
+    ///
+    /// 撤单成功后查询订单的最终成交，订单移入 `revoked` 并通知引擎结算成交部分；查询失败时
+    /// 等待撤单回报结算。撤单失败时订单可能已成交，间隔一段时间后先查询一次订单状态，
+    /// 已完成的订单交由订单完成处理，仍未完成时再撤单。
+    async fn revoke(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) -> bool {
+        let Some(order_id) = self.order_id.clone() else {
+            return true;
+        };
+        let Some(id) = orders.read().await.get(&order_id).map(|o| o.id) else {
+            self.order_id = None;
+            return true;
+        };
+        if let Some(retry) = self.revoke_retry {
+            if Instant::now() < retry {
+                return false;
+            }
+            self.revoke_retry = Some(Instant::now() + REVOKE_BACKOFF);
+            match self.query_order(exchange, orders, &order_id, id).await {
+                Some(false) => {}
+                Some(true) => {
+                    tracing::info!("Instance {} order {} already done", self.id, order_id);
+                    self.notify_done(&order_id);
+                    return false;
+                }
+                None => return false,
+            }
+        }
+
+        let action = RevokeOrder {
+            symbol: self.symbol.clone(),
+            order_id: id,
+        };
+        match action.revoke_order(exchange).await {
+            Ok(_) => {
+                tracing::info!("Instance {} revoked order {}", self.id, order_id);
+                self.order_id = None;
+                self.revoke_retry = None;
+                self.revoked.insert(order_id.clone());
+                if self.query_order(exchange, orders, &order_id, id).await != Some(true) {
+                    return false;
+                }
+                self.notify_done(&order_id);
+                true
+            }
+            Err(e) => {
+                tracing::error!("Instance {} revoke order failed, {:?}", self.id, e);
+                self.revoke_retry = Some(Instant::now() + REVOKE_BACKOFF);
+                false
+            }
+        }
+    }
+
+    // 查询订单并同步到订单表，返回订单是否已完成，查询失败时返回 None
+    async fn query_order(
+        &self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+        order_id: &OrderId,
+        id: u64,
+    ) -> Option<bool> {
+        let request = OrderStatusRequest {
+            symbol: self.symbol.clone(),
+            order_id: Some(id),
+            ..Default::default()
+        };
+        match exchange.order_status(request).await {
+            Ok(rest) => {
+                let mut orders = orders.write().await;
+                let order = orders.get_mut(order_id)?;
+                order.sync(&rest);
+                Some(!order.status.is_open())
+            }
+            Err(e) => {
+                tracing::error!("Instance {} query order {} failed, {:?}", self.id, id, e);
+                None
+            }
+        }
+    }
+
+    // 通知引擎按订单完成处理，已撤销订单的成交部分随之结算
+    fn notify_done(&self, order_id: &OrderId) {
+        if let Some(order_done) = &self.order_done {
+            if let Err(e) = order_done.send(order_id.clone()) {
+                tracing::error!("Instance {} send order done failed, {:?}", self.id, e);
+            }
+        }
+    }
+
+    // 市价卖出持仓，`quantity` 为按交易规则修正后的数量
+    async fn market_sell(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+        position: &Position,
+        quantity: f64,
+        price: f64,
+    ) -> bool {
+        let client_order_id = self.next_client_order_id();
+        let action = MarketSell {
+            symbol: self.symbol.clone(),
+            quantity,
+            client_order_id: client_order_id.clone(),
+        };
+        match self.submit(exchange, &action, &client_order_id).await {
+            Ok((id, ts)) => {
+                tracing::info!("Instance {} placed market sell order {}", self.id, id);
+                self.register(
+                    orders,
+                    Order {
+                        inst_id: self.id.clone(),
+                        symbol: self.symbol.clone(),
+                        id,
+                        client_order_id,
+                        strategies: Vec::new(),
+                        side: OrderSide::Sell,
+                        quality: action.quantity,
+                        price,
+                        buy_price: position.buy_price,
+                        min_sell_price: position.min_sell_price,
+                        status: OrderStatus::Committed,
+                        filled: Fill::default(),
+                        update_ts: ts,
+                    },
+                )
+                .await;
+                true
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Instance {} place market sell order failed, {:?}",
+                    self.id,
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    // 已部分成交的挂单只撤单，保留订单ID，撤单回报到达后按成交部分结算
+    async fn revoke_filled(&self, exchange: &Exchanges, order: &Order) {
+        tracing::info!(
+            "Instance {} revoke partially filled order {}",
+            self.id,
+            order.id
+        );
+        let action = RevokeOrder {
+            symbol: self.symbol.clone(),
+            order_id: order.id,
+        };
+        if let Err(e) = action.revoke_order(exchange).await {
+            tracing::error!("Instance {} revoke order failed, {:?}", self.id, e);
+        }
+    }
+
+    /// 风控停止交易：只撤销买单，挂着的卖单保留，持仓照常卖出与止损。
+    pub(crate) async fn halt(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) {
+        let selling = match &self.order_id {
+            Some(order_id) => orders
+                .read()
+                .await
+                .get(order_id)
+                .is_some_and(|o| o.side == OrderSide::Sell),
+            None => false,
+        };
+        if !selling {
+            self.cancel(exchange, orders).await;
+        }
+    }
+
+    /// 撤销当前挂单，买单释放资金回到 WaitBuy，持仓保留。
+    pub(crate) async fn cancel(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) {
+        let order = match &self.order_id {
+            Some(order_id) => orders.read().await.get(order_id).cloned(),
+            None => None,
+        };
+        if let Some(order) = order.as_ref().filter(|o| o.filled.quantity > 0.) {
+            self.revoke_filled(exchange, order).await;
+            return;
+        }
+        if !self.revoke(exchange, orders).await {
+            return;
+        }
+        self.sell_pending = None;
+        if let Some(order) = order.filter(|o| o.side == OrderSide::Buy) {
+            // 撤单前已有成交的买单由订单完成处理建立持仓
+            if self.revoked_filled(orders, &order).await > 0. {
+                return;
+            }
+            self.capital
+                .write()
+                .await
+                .release(&self.id, order.quality * order.price);
+            *self.state.write().await = State::WaitBuy;
+        }
+    }
+
+    /// 退出时平仓：按买一价市价卖出全部持仓，需在挂单撤销并结算后调用。
+    pub(crate) async fn flatten(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) {
+        let Some(position) = self.position.clone() else {
+            return;
+        };
+        if self.order_id.is_some() || !self.revoked.is_empty() {
+            tracing::error!("Instance {} has an unsettled order, skip flatten", self.id);
+            return;
+        }
+        let price = match self.optimal_price.read().await.get(&self.symbol) {
+            Some(price) => price.buy,
+            None => position.buy_price,
+        };
+        let quantity = match self.filter.market(price, position.quantity) {
+            Ok(quantity) => quantity,
+            Err(e) => {
+                tracing::error!("Instance {} flatten order rejected, {}", self.id, e);
+                return;
+            }
+        };
+        tracing::info!("Instance {} flatten position {}", self.id, quantity);
+        if self
+            .market_sell(exchange, orders, &position, quantity, price)
+            .await
+        {
+            self.stopping = true;
+        }
+    }
+
+    /// 手动平仓：撤销当前挂单后市价卖出全部持仓，返回市价卖单ID。
+    pub(crate) async fn close(
+        &mut self,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) -> Result<OrderId, String> {
+        if self.position.is_none() {
+            return Err(format!("Instance {} has no position", self.id));
+        }
+        if self.stopping {
+            return Err(format!("Instance {} is already closing", self.id));
+        }
+        self.cancel(exchange, orders).await;
+        if let Some(order_id) = self.order_id.as_ref().or(self.revoked.iter().next()) {
+            return Err(format!(
+                "Instance {} order {} is pending, retry later",
+                self.id, order_id
+            ));
+        }
+        self.flatten(exchange, orders).await;
+        match &self.order_id {
+            Some(order_id) if self.stopping => Ok(order_id.clone()),
+            _ => Err(format!("Instance {} close failed, see logs", self.id)),
+        }
+    }
+
+    /// 挂单超时未成交：按配置撤单、按最新报价重新挂单或转为市价卖出，追价次数用完后撤单。
+    pub(crate) async fn on_order_stale(
+        &mut self,
+        order: &Order,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) {
+        if self.order_id != Some(order.id.to_string()) || self.stopping {
+            return;
+        }
+        if order.filled.quantity > 0. {
+            self.revoke_filled(exchange, order).await;
+            return;
+        }
+        let action = match (self.stale_order.action, &self.position, &order.side) {
+            (StaleAction::Chase, _, _) if self.chases < self.stale_order.max_chase => {
+                StaleAction::Chase
+            }
+            (StaleAction::Market, Some(_), OrderSide::Sell) => StaleAction::Market,
+            _ => StaleAction::Cancel,
+        };
+        tracing::info!(
+            "Instance {} order {} is stale, {:?}",
+            self.id,
+            order.id,
+            action
+        );
+
+        if !self.revoke(exchange, orders).await {
+            return;
+        }
+        // 撤单前可能已有成交，成交部分由订单完成处理结算
+        let filled = self.revoked_filled(orders, order).await;
+        if let (StaleAction::Market, Some(position)) = (action, self.position.clone()) {
+            self.chases = 0;
+            // 市价卖出剩余的持仓，数量不满足交易规则时只撤单
+            match self.filter.market(order.price, position.quantity - filled) {
+                Ok(quantity) => {
+                    self.market_sell(exchange, orders, &position, quantity, order.price)
+                        .await;
+                }
+                Err(e) => tracing::error!("Instance {} market sell rejected, {}", self.id, e),
+            }
+            return;
+        }
+        if filled > 0. {
+            tracing::info!(
+                "Instance {} order {} filled {} before revoked",
+                self.id,
+                order.id,
+                filled
+            );
+            self.chases = 0;
+            return;
+        }
+
+        if order.side == OrderSide::Buy {
+            self.capital
+                .write()
+                .await
+                .release(&self.id, order.quality * order.price);
+            self.sell_pending = None;
+            *self.state.write().await = State::WaitBuy;
+        }
+        if action != StaleAction::Chase {
+            self.chases = 0;
+            return;
+        }
+        self.chases += 1;
+        match order.side {
+            OrderSide::Buy => self.buy(exchange, orders, order.price).await,
+            OrderSide::Sell => self.sell(exchange, orders, order.price).await,
+        }
+    }
+
+    /// 订单成交或撤销后的状态流转，卖单有成交时返回已实现盈亏。
+    pub(crate) async fn on_order_done(
+        &mut self,
+        order: &Order,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) -> Option<f64> {
+        // 已撤销的订单只结算成交部分，不影响当前挂单与止损状态
+        if self.revoked.remove(&order.id.to_string()) {
+            return self.settle_revoked(order).await;
+        }
+        // 下单响应丢失时，订单回报按客户端订单ID认领
+        let adopted = self.order_id.is_none()
+            && self.client_order_id.as_deref() == Some(order.client_order_id.as_str());
+        if self.order_id != Some(order.id.to_string()) && !adopted {
+            return None;
+        }
+        self.order_id = None;
+        self.revoke_retry = None;
+        self.chases = 0;
+        if adopted {
+            tracing::info!("Instance {} adopted order {}", self.id, order.id);
+        }
+
+        match (&order.side, order.status) {
+            // 全部成交，或撤销、过期前已部分成交，按成交部分建立持仓
+            (OrderSide::Buy, _) if order.filled.quantity > 0. => {
+                self.open_position(order, adopted).await;
+                if let Some(price) = self.sell_pending.take() {
+                    self.sell(exchange, orders, price).await;
+                }
+                None
+            }
+            (OrderSide::Buy, _) => {
+                if order.status == OrderStatus::Rejected {
+                    tracing::error!("Instance {} buy order {} rejected", self.id, order.id);
+                }
+                if !adopted {
+                    self.capital
+                        .write()
+                        .await
+                        .release(&self.id, order.quality * order.price);
+                }
+                self.sell_pending = None;
+                *self.state.write().await = State::WaitBuy;
+                None
+            }
+            (OrderSide::Sell, OrderStatus::Success) => {
+                if self.stopping {
+                    tracing::info!("Instance {} stopped loss", self.id);
+                } else {
+                    tracing::info!("Instance {} finished a trade", self.id);
+                }
+                self.stopping = false;
+                *self.state.write().await = State::WaitBuy;
+                // 撤销的卖单先于市价卖单结算，持仓只剩本单卖出的部分
+                let position = self.position.take()?;
+                self.capital.write().await.release(&self.id, position.cost);
+                let pnl = order.received_quote() - position.cost - position.fee;
+                self.pnl.realized += pnl;
+                self.pnl.commission += order.commission_value();
+                self.pnl.unrealized = 0.;
+                Some(pnl)
+            }
+            // 卖单撤销、拒绝或过期，扣除已卖出的部分，保留剩余持仓等待下一次卖出信号
+            (OrderSide::Sell, _) => {
+                // 止损单未能全部成交时允许再次触发止损
+                self.stopping = false;
+                self.reduce_position(order).await
+            }
+        }
+    }
+
+    // 结算撤单前已成交的部分：买单按成交建立持仓，卖单扣除已卖出的持仓并返回已实现盈亏
+    async fn settle_revoked(&mut self, order: &Order) -> Option<f64> {
+        if order.filled.quantity <= 0. {
+            return None;
+        }
+        tracing::info!(
+            "Instance {} settle revoked order {} with {} filled",
+            self.id,
+            order.id,
+            order.filled.quantity
+        );
+        match order.side {
+            OrderSide::Buy => {
+                self.open_position(order, false).await;
+                None
+            }
+            OrderSide::Sell => self.reduce_position(order).await,
+        }
+    }
+
+    // 按买单的成交建立持仓，释放未成交部分占用的资金
+    async fn open_position(&mut self, order: &Order, adopted: bool) {
+        let buy_price = order.fill_price();
+        let cost = order.filled.quantity * buy_price;
+        {
+            let mut capital = self.capital.write().await;
+            if adopted {
+                if let Err(e) = capital.lock(&self.id, cost) {
+                    tracing::error!("Instance {} lock capital failed, {}", self.id, e);
+                }
+            } else {
+                // 释放未成交部分及成交价低于挂单价的差额
+                capital.release(&self.id, (order.quality * order.price - cost).max(0.));
+            }
+        }
+        if order.status != OrderStatus::Success {
+            tracing::info!(
+                "Instance {} buy order {} {:?} with {} filled",
+                self.id,
+                order.id,
+                order.status,
+                order.filled.quantity
+            );
+        }
+        *self.state.write().await = State::WaitSell;
+        let commission = order.commission_value();
+        self.pnl.commission += commission;
+        self.position = Some(Position {
+            quantity: order.received_quantity(),
+            buy_price,
+            min_sell_price: min_sell_price(buy_price),
+            cost,
+            fee: if order.commission_in_base() {
+                0.
+            } else {
+                commission
+            },
+        });
+    }
+
+    // 卖单撤销、拒绝或过期时扣除已卖出的部分，按比例结转成本与买入手续费
+    async fn reduce_position(&mut self, order: &Order) -> Option<f64> {
+        let position = self.position.as_mut()?;
+        if order.filled.quantity <= 0. {
+            return None;
+        }
+        let sold = order.filled.quantity.min(position.quantity);
+        let cost = position.cost * sold / position.quantity;
+        let fee = position.fee * sold / position.quantity;
+        position.quantity -= sold;
+        position.cost -= cost;
+        position.fee -= fee;
+        tracing::info!(
+            "Instance {} sell order {} {:?} with {} filled, {} left",
+            self.id,
+            order.id,
+            order.status,
+            sold,
+            position.quantity
+        );
+        self.capital.write().await.release(&self.id, cost);
+        let pnl = order.received_quote() - cost - fee;
+        self.pnl.realized += pnl;
+        self.pnl.commission += order.commission_value();
+        Some(pnl)
+    }
+}
+
+/// 实例的客户端订单ID前缀：实例ID中的前 16 个字母与数字，币安限制ID长度不超过36位。
+/// 不同的实例ID可能得到相同的前缀，启动与热加载时校验。
+pub(crate) fn client_order_prefix(inst_id: &str) -> String {
+    let id = inst_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .take(16)
+        .collect::<String>();
+    format!("bq{}-", id)
+}
+
+/// 策略在日志与指标中的名称，例如 rsi_1m
+fn strategy_label(strategy: &Strategies) -> String {
+    match strategy.data_category() {
+        Category::Kline(interval) => format!("{}_{}", strategy.name(), interval),
+        Category::BookTicker => strategy.name().to_string(),
+    }
+}
+
+/// 下单的最大尝试次数，包括下单失败后的订单查询
+const PLACE_ATTEMPTS: usize = 3;
+/// 下单失败后查询订单的间隔，按尝试次数递增
+const PLACE_BACKOFF: Duration = Duration::from_millis(500);
+/// 撤单失败后查询订单状态的间隔
+const REVOKE_BACKOFF: Duration = Duration::from_secs(5);
+
+/// 单次查询K线的最大数量
+const MAX_KLINES: usize = 1000;
+
+// 网络错误与交易所服务异常时请求结果未知，可以重试
+fn is_retryable(err: &binance::errors::Error) -> bool {
+    use binance::errors::Error;
+    matches!(
+        err,
+        Error::ReqError(_)
+            | Error::IoError(_)
+            | Error::InternalServerError
+            | Error::ServiceUnavailable
+    )
+}
+
+/// 订单不存在的错误码
+pub(crate) const ORDER_NOT_FOUND: i32 = -2013;
+
+// 交易所确认订单不存在
+pub(crate) fn is_order_missing(err: &binance::errors::Error) -> bool {
+    matches!(
+        err,
+        binance::errors::Error::BinanceError { response } if response.code == ORDER_NOT_FOUND
+    )
+}
+
+/// 取最近的已收盘K线写入策略缓冲，返回写入的K线数量。已在缓冲中的K线由策略跳过，
+/// 启动预热与断线后补齐共用。
+async fn warm_up_strategy(
+    exchange: &Exchanges,
+    symbol: &str,
+    strategy: &RwLock<Strategies>,
+) -> binance::errors::Result<usize> {
+    let (interval, bars) = {
+        let strategy = strategy.read().await;
+        let Category::Kline(interval) = strategy.data_category() else {
+            return Ok(0);
+        };
+        (interval, strategy.warmup_bars())
+    };
+    if bars == 0 {
+        return Ok(0);
+    }
+    // 多取一根，最后一根可能尚未收盘
+    let limit = (bars + 1).min(MAX_KLINES) as u16;
+    let klines = exchange.klines(symbol, &interval, limit).await?;
+    let now = now() as i64;
+    let mut strategy = strategy.write().await;
+    let mut fed = 0;
+    for summary in klines.into_iter().filter(|k| k.close_time < now) {
+        let _ = strategy.signal(Data::Kline(kline_event(symbol, &interval, summary)));
+        fed += 1;
+    }
+    Ok(fed)
+}
+
+// 将已收盘的历史K线转换为行情推送的格式
+fn kline_event(symbol: &str, interval: &KlineInterval, summary: KlineSummary) -> KlineEvent {
+    KlineEvent {
+        event_time: summary.close_time as u64,
+        symbol: symbol.to_string(),
+        kline: Kline {
+            start_time: summary.open_time,
+            end_time: summary.close_time,
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            first_trade_id: 0,
+            last_trade_id: 0,
+            open: summary.open,
+            close: summary.close,
+            high: summary.high,
+            low: summary.low,
+            volume: summary.volume,
+            number_of_trades: summary.number_of_trades,
+            is_final_bar: true,
+            quote_volume: summary.quote_asset_volume,
+            active_buy_volume: summary.taker_buy_base_asset_volume,
+            active_volume_buy_quote: summary.taker_buy_quote_asset_volume,
+            ignore_me: String::new(),
+        },
+    }
+}
+
+// 按买单及其后的订单估算持仓，手续费按默认费率从数量中扣除，扣除之后撤销或过期的卖单已卖出的部分。
+// 同时返回估算数量允许的误差，实际手续费可能以其他资产支付或享有折扣。
+fn rebuild_position(buy: &RestOrder, later: &[RestOrder]) -> (Position, f64) {
+    let buy_price = buy.cummulative_quote_qty / buy.executed_qty;
+    let mut position = Position {
+        quantity: buy.executed_qty * (1. - FEE_RATE),
+        buy_price,
+        min_sell_price: min_sell_price(buy_price),
+        cost: buy.cummulative_quote_qty,
+        fee: 0.,
+    };
+    let sells = later.iter().filter(|o| {
+        o.side == OrderSide::Sell && o.executed_qty > 0. && !OrderStatus::from(&o.status).is_open()
+    });
+    for sell in sells {
+        let sold = sell.executed_qty.min(position.quantity);
+        position.cost -= position.cost * sold / position.quantity;
+        position.quantity -= sold;
+    }
+    (position, buy.executed_qty * FEE_RATE * 2.)
+}
+
+/// 保本价：覆盖买卖两次手续费
+pub(crate) fn min_sell_price(buy_price: f64) -> f64 {
+    buy_price * (1. + FEE_RATE) / (1. - FEE_RATE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc, time::Instant};
+
+    use binance::rest_model::{
+        Order as RestOrder, OrderSide, OrderStatus as RestOrderStatus, OrderType, TimeInForce,
+    };
+    use strategies::Signal::{Buy, Nothing, Sell};
+    use tokio::sync::{mpsc, RwLock};
+
+    use super::{Decision, Instance, Position, StaleAction, State, StrategyMode};
+    use crate::{
+        capital::Capital,
+        config::StaleOrder,
+        exchange::{Exchanges, Paper},
+        Fill, Order, OrderId, OrderStatus, Price,
+    };
+
+    fn instance() -> Instance {
+        let mut capital = Capital::new(100.);
+        capital.reserve("inst-1", 100.).unwrap();
+        Instance::new(
+            "inst-1",
+            "BTCUSDT",
+            StrategyMode::Or,
+            0.5,
+            0.1,
+            Arc::new(RwLock::new(capital)),
+            vec![],
+        )
+    }
+
+    // 模拟订单回报：查询订单的最新状态后按订单完成处理
+    async fn settle(
+        inst: &mut Instance,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+        order_id: &OrderId,
+    ) -> Option<f64> {
+        inst.query_order(exchange, orders, order_id, order_id.parse().unwrap())
+            .await;
+        let order = orders.read().await[order_id].clone();
+        inst.on_order_done(&order, exchange, orders).await
+    }
+
+    // 以 10 买入 10 个，持仓扣除默认费率的手续费后为 9.99
+    async fn buy_filled(
+        inst: &mut Instance,
+        exchange: &Exchanges,
+        orders: &RwLock<HashMap<OrderId, Order>>,
+    ) {
+        let Exchanges::Paper(paper) = exchange else {
+            unreachable!()
+        };
+        inst.buy(exchange, orders, 10.).await;
+        let order_id = inst.order_id.clone().unwrap();
+        paper.trade(order_id.parse().unwrap(), 10., 10.).await;
+        settle(inst, exchange, orders, &order_id).await;
+        assert!((inst.position.as_ref().unwrap().quantity - 9.99).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_decide() {
+        let weights = [1., 1., 2.];
+
+        assert_eq!(
+            StrategyMode::Or.decide(&[Nothing, Buy, Nothing], &weights, 0.),
+            Buy
+        );
+        assert_eq!(
+            StrategyMode::Or.decide(&[Sell, Buy, Nothing], &weights, 0.),
+            Nothing
+        );
+
+        assert_eq!(
+            StrategyMode::And.decide(&[Buy, Buy, Buy], &weights, 0.),
+            Buy
+        );
+        assert_eq!(
+            StrategyMode::And.decide(&[Buy, Nothing, Buy], &weights, 0.),
+            Nothing
+        );
+
+        assert_eq!(
+            StrategyMode::Weight.decide(&[Nothing, Nothing, Sell], &weights, 0.5),
+            Sell
+        );
+        assert_eq!(
+            StrategyMode::Weight.decide(&[Buy, Nothing, Nothing], &weights, 0.5),
+            Nothing
+        );
+        assert_eq!(
+            StrategyMode::Weight.decide(&[Buy, Buy, Sell], &weights, 0.5),
+            Nothing
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconcile() {
+        let exchange = Exchanges::Paper(Paper::new("USDT", 100.));
+        let mut inst = instance();
+        inst.buy(&exchange, &RwLock::new(HashMap::new()), 10.).await;
+        let order_id = inst.order_id.clone();
+        assert!(order_id.is_some());
+        assert_eq!(inst.client_order_id.as_deref(), Some("bqinst1-1"));
+
+        // 重启后从交易所恢复挂单
+        let mut restarted = instance();
+        let orders = RwLock::new(HashMap::new());
+        restarted.reconcile(&exchange, &orders).await.unwrap();
+        assert_eq!(restarted.order_id, order_id);
+        assert_eq!(restarted.seq, 1);
+        assert_eq!(restarted.next_client_order_id(), "bqinst1-2");
+        assert_eq!(*restarted.state.read().await, State::WaitSell);
+        assert!(orders.read().await.contains_key(&order_id.unwrap()));
+        assert_eq!(restarted.capital.read().await.available("inst-1"), 0.);
+    }
+
+    #[tokio::test]
+    async fn test_order_stale() {
+        let exchange = Exchanges::Paper(Paper::new("USDT", 100.));
+        let orders = RwLock::new(HashMap::new());
+        let mut inst = instance().with_stale_order(StaleOrder {
+            timeout: 60,
+            action: StaleAction::Chase,
+            max_chase: 1,
+        });
+        inst.buy(&exchange, &orders, 10.).await;
+        let first = orders.read().await[inst.order_id.as_ref().unwrap()].clone();
+
+        // 追价：撤单后重新挂单
+        inst.on_order_stale(&first, &exchange, &orders).await;
+        assert_eq!(
+            orders.read().await[&first.id.to_string()].status,
+            OrderStatus::Canceled
+        );
+        assert_eq!(inst.chases, 1);
+        let second = orders.read().await[inst.order_id.as_ref().unwrap()].clone();
+        assert_ne!(second.id, first.id);
+
+        // 追价次数用完后撤单
+        inst.on_order_stale(&second, &exchange, &orders).await;
+        assert_eq!(inst.order_id, None);
+        assert_eq!(inst.chases, 0);
+        assert_eq!(*inst.state.read().await, State::WaitBuy);
+        assert_eq!(inst.capital.read().await.available("inst-1"), 100.);
+    }
+
+    #[tokio::test]
+    async fn test_partial_fill() {
+        let exchange = Exchanges::Paper(Paper::new("USDT", 100.));
+        let orders = RwLock::new(HashMap::new());
+        let mut inst = instance();
+        inst.buy(&exchange, &orders, 10.).await;
+        assert_eq!(inst.capital.read().await.available("inst-1"), 0.);
+
+        // 买单成交一半后撤销，按成交部分建立持仓并释放剩余资金
+        let mut order = orders.read().await[inst.order_id.as_ref().unwrap()].clone();
+        order.status = OrderStatus::Canceled;
+        order.filled = Fill {
+            quantity: 5.,
+            avg_price: 10.,
+            commission: 0.005,
+            commission_asset: "BTC".to_string(),
+            ..Default::default()
+        };
+        inst.on_order_done(&order, &exchange, &orders).await;
+        let position = inst.position.clone().unwrap();
+        assert_eq!(position.quantity, 4.995);
+        assert_eq!(position.cost, 50.);
+        assert_eq!(inst.capital.read().await.available("inst-1"), 50.);
+        assert_eq!(*inst.state.read().await, State::WaitSell);
+
+        // 卖单部分成交后过期，保留剩余持仓
+        order.id = 0;
+        order.side = OrderSide::Sell;
+        order.status = OrderStatus::Expired;
+        order.filled.quantity = 0.999;
+        inst.order_id = Some(order.id.to_string());
+        inst.on_order_done(&order, &exchange, &orders).await;
+        let position = inst.position.clone().unwrap();
+        assert!((position.quantity - 3.996).abs() < 1e-9);
+        assert!((position.cost - 40.).abs() < 1e-9);
+        assert_eq!(*inst.state.read().await, State::WaitSell);
+    }
+
+    #[tokio::test]
+    async fn test_pnl() {
+        let exchange = Exchanges::Paper(Paper::new("USDT", 100.));
+        let orders = RwLock::new(HashMap::new());
+        let mut inst = instance();
+        inst.buy(&exchange, &orders, 10.).await;
+
+        // 买入手续费以 BNB 支付，不从持仓数量中扣除，计入持仓成本
+        let mut order = orders.read().await[inst.order_id.as_ref().unwrap()].clone();
+        order.status = OrderStatus::Success;
+        order.filled = Fill {
+            quantity: 10.,
+            avg_price: 10.,
+            commission: 0.0002,
+            commission_asset: "BNB".to_string(),
+            commission_quote: Some(0.1),
+            ..Default::default()
+        };
+        inst.on_order_done(&order, &exchange, &orders).await;
+        let position = inst.position.clone().unwrap();
+        assert_eq!(position.quantity, 10.);
+        assert_eq!(position.fee, 0.1);
+        assert!((inst.mark(11.) - 9.9).abs() < 1e-9);
+
+        // 卖出手续费以计价资产支付
+        order.id = 0;
+        order.side = OrderSide::Sell;
+        order.filled = Fill {
+            quantity: 10.,
+            avg_price: 11.,
+            commission: 0.11,
+            commission_asset: "USDT".to_string(),
+            ..Default::default()
+        };
+        inst.order_id = Some(order.id.to_string());
+        let pnl = inst
+            .on_order_done(&order, &exchange, &orders)
+            .await
+            .unwrap();
+        assert!((pnl - 9.79).abs() < 1e-9);
+        assert!((inst.pnl.realized - 9.79).abs() < 1e-9);
+        assert!((inst.pnl.commission - 0.21).abs() < 1e-9);
+        assert_eq!(inst.pnl.unrealized, 0.);
+    }
+
+    #[tokio::test]
+    async fn test_rebuild() {
+        let rest_order = |id, side, status, executed_qty: f64| RestOrder {
+            symbol: "BTCUSDT".to_string(),
+            order_id: id,
+            order_list_id: -1,
+            client_order_id: format!("bqinst1-{}", id),
+            price: 10.,
+            orig_qty: 10.,
+            executed_qty,
+            cummulative_quote_qty: executed_qty * 10.,
+            status,
+            time_in_force: TimeInForce::GTC,
+            order_type: OrderType::Limit,
+            side,
+            stop_price: 0.,
+            iceberg_qty: 0.,
+            time: id,
+            update_time: id,
+            is_working: false,
+            orig_quote_order_qty: 0.,
+        };
+        let history = [
+            rest_order(1, OrderSide::Buy, RestOrderStatus::Filled, 10.),
+            rest_order(2, OrderSide::Sell, RestOrderStatus::Canceled, 4.),
+        ];
+        let journaled = Position {
+            quantity: 6.,
+            buy_price: 10.,
+            min_sell_price: 10.02,
+            cost: 60.,
+            fee: 0.1,
+        };
+
+        // 状态日志已结算部分成交的卖单，沿用其数量与手续费
+        let mut inst = instance();
+        inst.position = Some(journaled.clone());
+        inst.rebuild(&history, &RwLock::new(HashMap::new())).await;
+        let position = inst.position.clone().unwrap();
+        assert_eq!(position.quantity, 6.);
+        assert_eq!(position.fee, 0.1);
+
+        // 状态日志没有记录部分成交的卖单，按交易所历史扣除已卖出的部分
+        inst.position = Some(Position {
+            quantity: 10.,
+            cost: 100.,
+            ..journaled
+        });
+        inst.rebuild(&history, &RwLock::new(HashMap::new())).await;
+        let position = inst.position.clone().unwrap();
+        assert!((position.quantity - 5.99).abs() < 1e-9);
+        assert!((position.cost - 100. * 5.99 / 9.99).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_register() {
+        let orders = RwLock::new(HashMap::new());
+        let mut inst = instance();
+        let order = Order {
+            inst_id: "inst-1".to_string(),
+            symbol: "BTCUSDT".to_string(),
+            id: 7,
+            client_order_id: "bqinst1-1".to_string(),
+            strategies: vec!["rsi-1".to_string()],
+            side: OrderSide::Buy,
+            quality: 1.,
+            price: 10.,
+            buy_price: 10.,
+            min_sell_price: 10.02,
+            status: OrderStatus::Committed,
+            filled: Fill::default(),
+            update_ts: 0,
+        };
+
+        // 订单回报先于下单响应到达并已路由登记，保留回报中的状态与成交
+        let mut routed = order.clone();
+        routed.strategies = Vec::new();
+        routed.status = OrderStatus::PartiallyFilled;
+        routed.filled.quantity = 0.5;
+        orders.write().await.insert("7".to_string(), routed);
+        inst.register(&orders, order).await;
+        let registered = orders.read().await["7"].clone();
+        assert_eq!(registered.status, OrderStatus::PartiallyFilled);
+        assert_eq!(registered.filled.quantity, 0.5);
+        assert_eq!(registered.strategies, vec!["rsi-1".to_string()]);
+        assert_eq!(inst.order_id.as_deref(), Some("7"));
+    }
+
+    #[tokio::test]
+    async fn test_halt() {
+        let exchange = Exchanges::Paper(Paper::new("USDT", 100.));
+        let orders = RwLock::new(HashMap::new());
+        let mut inst = instance();
+        inst.buy(&exchange, &orders, 10.).await;
+        let order_id = inst.order_id.clone().unwrap();
+
+        // 未成交的买单撤销后释放资金
+        inst.halt(&exchange, &orders).await;
+        assert_eq!(inst.order_id, None);
+        assert_eq!(orders.read().await[&order_id].status, OrderStatus::Canceled);
+        assert_eq!(inst.capital.read().await.available("inst-1"), 100.);
+        assert_eq!(*inst.state.read().await, State::WaitBuy);
+
+        // 已部分成交的挂单保留订单ID，等待撤单回报结算
+        inst.buy(&exchange, &orders, 10.).await;
+        let order_id = inst.order_id.clone().unwrap();
+        orders
+            .write()
+            .await
+            .get_mut(&order_id)
+            .unwrap()
+            .filled
+            .quantity = 1.;
+        inst.halt(&exchange, &orders).await;
+        assert_eq!(inst.order_id, Some(order_id));
+    }
+
+    #[tokio::test]
+    async fn test_halt_sell() {
+        let exchange = Exchanges::Paper(Paper::new("USDT", 100.));
+        let orders = RwLock::new(HashMap::new());
+        let decision = |signal| Decision {
+            inst_id: "inst-1".to_string(),
+            signal,
+            price: 12.,
+            strategies: Vec::new(),
+        };
+        let mut inst = instance();
+        buy_filled(&mut inst, &exchange, &orders).await;
+
+        // 停止交易后卖出决策照常下单，挂着的卖单不撤销
+        inst.halt(&exchange, &orders).await;
+        assert!(
+            inst.on_decision(decision(Sell), true, &exchange, &orders)
+                .await
+        );
+        let sell_id = inst.order_id.clone().unwrap();
+        assert_eq!(orders.read().await[&sell_id].side, OrderSide::Sell);
+        inst.halt(&exchange, &orders).await;
+        assert_eq!(inst.order_id.as_ref(), Some(&sell_id));
+        assert_eq!(orders.read().await[&sell_id].status, OrderStatus::Committed);
+
+        // 买入决策跳过
+        let mut idle = instance();
+        assert!(
+            !idle
+                .on_decision(decision(Buy), true, &exchange, &orders)
+                .await
+        );
+        assert_eq!(idle.order_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_stop_loss_partial_fill() {
+        let exchange = Exchanges::Paper(Paper::new("USDT", 100.));
+        let Exchanges::Paper(paper) = &exchange else {
+            unreachable!()
+        };
+        let orders = RwLock::new(HashMap::new());
+        let (order_done_tx, mut order_done_rx) = mpsc::unbounded_channel();
+        let mut inst = instance().with_order_done(order_done_tx);
+        buy_filled(&mut inst, &exchange, &orders).await;
+
+        // 卖单成交 4 个后触发止损，撤单后按查询到的成交只卖出剩余的持仓
+        inst.sell(&exchange, &orders, 12.).await;
+        let sell_id = inst.order_id.clone().unwrap();
+        paper.trade(sell_id.parse().unwrap(), 4., 12.).await;
+        paper.set_price("BTCUSDT", 8.).await;
+        inst.check_stop_loss(&exchange, &orders, 8.).await;
+        assert_eq!(order_done_rx.try_recv().unwrap(), sell_id);
+        let revoked = orders.read().await[&sell_id].clone();
+        assert_eq!(revoked.status, OrderStatus::Canceled);
+        assert_eq!(revoked.filled.quantity, 4.);
+        assert!(inst.stopping);
+        let market_id = inst.order_id.clone().unwrap();
+        assert!((orders.read().await[&market_id].quality - 5.99).abs() < 1e-9);
+
+        // 撤销的卖单按成交部分结算，重复的撤单回报不再结算
+        let pnl = settle(&mut inst, &exchange, &orders, &sell_id)
+            .await
+            .unwrap();
+        assert!((pnl - (48. * 0.999 - 100. * 4. / 9.99)).abs() < 1e-9);
+        assert!((inst.position.as_ref().unwrap().quantity - 5.99).abs() < 1e-9);
+        assert!(inst.stopping);
+        assert_eq!(inst.on_order_done(&revoked, &exchange, &orders).await, None);
+
+        // 市价卖单只计剩余持仓的成本
+        settle(&mut inst, &exchange, &orders, &market_id).await;
+        assert!(inst.position.is_none());
+        assert!((inst.pnl.realized - ((48. + 5.99 * 8.) * 0.999 - 100.)).abs() < 1e-9);
+        assert!((inst.capital.read().await.available("inst-1") - 100.).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_failed() {
+        let exchange = Exchanges::Paper(Paper::new("USDT", 100.));
+        let Exchanges::Paper(paper) = &exchange else {
+            unreachable!()
+        };
+        let orders = RwLock::new(HashMap::new());
+        let (order_done_tx, mut order_done_rx) = mpsc::unbounded_channel();
+        let mut inst = instance().with_order_done(order_done_tx);
+        buy_filled(&mut inst, &exchange, &orders).await;
+
+        // 卖单已成交但回报未到，撤单失败后间隔一段时间再查询
+        inst.sell(&exchange, &orders, 12.).await;
+        let sell_id = inst.order_id.clone().unwrap();
+        paper.trade(sell_id.parse().unwrap(), 9.99, 12.).await;
+        inst.check_stop_loss(&exchange, &orders, 8.).await;
+        assert!(inst.revoke_retry.is_some());
+        assert_eq!(inst.order_id.as_ref(), Some(&sell_id));
+        inst.check_stop_loss(&exchange, &orders, 8.).await;
+        assert!(order_done_rx.try_recv().is_err());
+
+        // 查询到订单已成交，交由订单完成处理
+        inst.revoke_retry = Some(Instant::now());
+        inst.check_stop_loss(&exchange, &orders, 8.).await;
+        assert_eq!(order_done_rx.try_recv().unwrap(), sell_id);
+        assert_eq!(orders.read().await[&sell_id].status, OrderStatus::Success);
+        assert!(!inst.stopping);
+        settle(&mut inst, &exchange, &orders, &sell_id).await;
+        assert!(inst.position.is_none());
+        assert_eq!(inst.revoke_retry, None);
+    }
+
+    #[tokio::test]
+    async fn test_limit_price() {
+        let optimal_price = Arc::new(RwLock::new(HashMap::new()));
+        let inst = instance().with_quotes(optimal_price.clone(), 0.1);
+        // 尚无报价时使用信号价格
+        assert_eq!(inst.limit_price(&OrderSide::Buy, 99.).await, 99.);
+        assert_eq!(inst.limit_price(&OrderSide::Sell, 99.).await, 99.);
+
+        optimal_price.write().await.insert(
+            "BTCUSDT".to_string(),
+            Price {
+                buy: 100.,
+                sell: 200.,
+            },
+        );
+        assert!((inst.limit_price(&OrderSide::Buy, 99.).await - 90.).abs() < 1e-9);
+        assert!((inst.limit_price(&OrderSide::Sell, 99.).await - 220.).abs() < 1e-9);
+    }
 }
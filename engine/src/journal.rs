@@ -0,0 +1,199 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+};
+
+use binance::{rest_model::OrderSide, ws_model::OrderUpdate};
+use serde::{Deserialize, Serialize};
+use strategies::Signal;
+
+use crate::{exchange::paper::split_symbol, instance::Decision, now, InstId, Order, Symbol};
+
+/// 交易日志的记录类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// 实例的交易决策
+    Decision,
+    /// 订单已挂单或已完成
+    Order,
+    /// 订单的一笔成交
+    Fill,
+}
+
+/// 交易日志中的一条记录，只追加不压缩，用于事后分析。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub ts: u64, // 时间戳，单位毫秒
+    pub kind: EntryKind,
+    pub inst_id: InstId,
+    pub symbol: Symbol,
+    pub strategies: Vec<String>, // 触发的策略ID
+    pub side: OrderSide,
+    pub order_id: Option<u64>,
+    pub status: Option<String>, // 订单状态
+    pub price: f64,
+    pub quantity: f64,
+    pub fee: f64, // 手续费，订单完成的记录折合计价资产
+    pub fee_asset: String,
+    pub pnl: Option<f64>, // 卖单完成后的已实现盈亏
+}
+
+impl Entry {
+    /// 交易决策，价格为产生信号时的价格
+    pub(crate) fn decision(decision: &Decision, symbol: &str) -> Option<Self> {
+        let side = match decision.signal {
+            Signal::Buy => OrderSide::Buy,
+            Signal::Sell => OrderSide::Sell,
+            Signal::Nothing => return None,
+        };
+        Some(Self {
+            ts: now(),
+            kind: EntryKind::Decision,
+            inst_id: decision.inst_id.clone(),
+            symbol: symbol.to_string(),
+            strategies: decision.strategies.clone(),
+            side,
+            order_id: None,
+            status: None,
+            price: decision.price,
+            quantity: 0.,
+            fee: 0.,
+            fee_asset: String::new(),
+            pnl: None,
+        })
+    }
+
+    /// 订单挂单时记录挂单价与数量，完成时记录成交均价、成交数量、折合计价资产的手续费与已实现盈亏
+    pub(crate) fn order(order: &Order) -> Self {
+        let done = !order.status.is_open();
+        let quote = split_symbol(&order.symbol).map_or("", |(_, quote)| quote);
+        Self {
+            ts: now(),
+            kind: EntryKind::Order,
+            inst_id: order.inst_id.clone(),
+            symbol: order.symbol.clone(),
+            strategies: order.strategies.clone(),
+            side: order.side.clone(),
+            order_id: Some(order.id),
+            status: Some(format!("{:?}", order.status)),
+            price: if done {
+                order.fill_price()
+            } else {
+                order.price
+            },
+            quantity: if done {
+                order.filled.quantity
+            } else {
+                order.quality
+            },
+            fee: if done { order.commission_value() } else { 0. },
+            fee_asset: if done {
+                quote.to_string()
+            } else {
+                String::new()
+            },
+            pnl: order.filled.pnl,
+        }
+    }
+
+    /// 订单回报中的一笔成交，手续费为实际支付的资产
+    pub(crate) fn fill(order: &Order, update: &OrderUpdate) -> Self {
+        Self {
+            ts: update.event_time,
+            kind: EntryKind::Fill,
+            inst_id: order.inst_id.clone(),
+            symbol: order.symbol.clone(),
+            strategies: order.strategies.clone(),
+            side: order.side.clone(),
+            order_id: Some(order.id),
+            status: Some(format!("{:?}", order.status)),
+            price: update.last_executed_price,
+            quantity: update.qty_last_executed,
+            fee: update.commission,
+            fee_asset: update.commission_asset.clone().unwrap_or_default(),
+            pnl: None,
+        }
+    }
+}
+
+/// 交易日志：决策、订单与成交以一行 JSON 追加到文件。
+pub(crate) struct Journal {
+    file: File,
+}
+
+impl Journal {
+    pub(crate) fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    pub(crate) fn append(&mut self, entry: &Entry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+/// 读取交易日志，跳过写了一半的行
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (no, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => tracing::error!("Skip broken entry at line {}, {:?}", no + 1, e),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use binance::rest_model::OrderSide;
+
+    use super::{read, Entry, EntryKind, Journal};
+
+    #[test]
+    fn test_journal() {
+        let path = std::env::temp_dir().join(format!("bq-journal-{}.jsonl", uuid::Uuid::new_v4()));
+        let entry = Entry {
+            ts: 1,
+            kind: EntryKind::Fill,
+            inst_id: "inst-1".to_string(),
+            symbol: "BTCUSDT".to_string(),
+            strategies: vec!["rsi-1".to_string()],
+            side: OrderSide::Buy,
+            order_id: Some(1),
+            status: Some("PartiallyFilled".to_string()),
+            price: 10.,
+            quantity: 1.,
+            fee: 0.001,
+            fee_asset: "BTC".to_string(),
+            pnl: None,
+        };
+        {
+            let mut journal = Journal::open(&path).unwrap();
+            journal.append(&entry).unwrap();
+            journal.append(&entry).unwrap();
+        }
+        // 崩溃时写了一半的行
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"{\"ts\":").unwrap();
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].kind, EntryKind::Fill);
+        assert_eq!(entries[1].strategies, vec!["rsi-1".to_string()]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,338 +1,1584 @@
 use std::{
-    any::Any,
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
     future::Future,
-    sync::{atomic::AtomicBool, Arc},
-    time::Duration,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use action::Handle;
 use binance::{
-    account::Account,
-    api::Binance,
-    userstream::UserStream,
-    websockets::{kline_stream, WebSockets},
-    ws_model::{CombinedStreamEvent, OrderUpdate, WebsocketEvent, WebsocketEventUntag},
+    account::OrderStatusRequest,
+    rest_model::{Order as RestOrder, OrderSide, OrderStatus as RestOrderStatus},
+    websockets::{book_ticker_stream, kline_stream},
+    ws_model::OrderUpdate,
 };
+use capital::{Capital, CapitalError};
 use channel::Mpsc;
-use config::Config;
-use instance::Instance;
-use strategies::{
-    atr::AverageTrueRange, rsi::RelativeStrengthIndex, Category, Data, DataCategory, DataId,
-    DataIndex, Index, Signal, Strategies, Strategy,
+use config::{Config, Instance as InstanceConfig, Shutdown};
+use control::{Command, Control, InstanceStatus, Reply, Status};
+use exchange::{paper::split_symbol, BinanceSpot, Channels, Exchange, Exchanges, Paper};
+use filter::SymbolFilter;
+use instance::{client_order_prefix, min_sell_price, Decision, Instance};
+use journal::{Entry, Journal};
+use metrics::METRICS;
+use risk::Risk;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use store::{Record, Snapshot, Store};
+use strategies::{Category, Data, DataIndex, Index};
+use tokio::{
+    sync::{
+        broadcast::error::RecvError,
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        RwLock,
+    },
+    time,
 };
-use tokio::{sync::RwLock, time};
 
 use crate::channel::Broadcast;
 
 mod action;
+pub mod capital;
 mod channel;
 pub mod config;
+pub mod control;
+pub mod exchange;
+pub mod filter;
 mod instance;
+pub mod journal;
+mod metrics;
+pub mod risk;
+mod store;
 
 type Symbol = String;
 type OrderId = String;
 type DataChannelIndex = Index<Symbol>;
+/// 数据通道，热加载时增删，数据流推送与实例订阅共用
+type DataChannels = Arc<Mutex<HashMap<DataChannelIndex, Broadcast<Data>>>>;
 type InstId = String;
 
+/// 现货默认手续费率
+pub(crate) const FEE_RATE: f64 = 0.001;
+
+/// 已完成订单在订单表中保留的时长，等待迟到的订单回报，单位毫秒
+const ARCHIVE_DELAY: u64 = 60_000;
+
+/// 过期挂单撤单失败后的重试间隔，单位毫秒
+const STALE_RETRY: u64 = 10_000;
+
+/// 从交易所同步 REST 请求额度用量并输出的间隔
+const RATE_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 退出时等待撤单与平仓订单回报的最长时间
+const SHUTDOWN_WAIT: Duration = Duration::from_secs(10);
+
 pub struct Engine {
-    account: Account,
-    user_stream: UserStream,
+    exchange: Arc<Exchanges>,
 
     state: State,
 
-    data_channels: HashMap<DataChannelIndex, Broadcast<Data>>,
+    data_channels: DataChannels,
     order_channel: Mpsc<OrderUpdate>,
-    wss: Option<WebSockets<'static, CombinedStreamEvent<WebsocketEventUntag>>>,
-    wss_streams: Vec<String>,
+    data_gap: Broadcast<()>,               // 数据流断线重连后的通知
+    reconnected: Broadcast<()>,            // 数据流重连后的通知，包括主动重连
+    stop: Broadcast<()>,                   // 退出时停止数据流
+    resubscribe: Broadcast<()>,            // 数据流变化后重新订阅
+    wss_streams: Arc<RwLock<Vec<String>>>, // 订阅的数据流
+
+    reload: Mpsc<Config>,                              // 热加载的配置
+    inst_configs: HashMap<InstId, InstanceConfig>,     // 各实例当前生效的配置
+    routes: Arc<RwLock<HashMap<String, InstId>>>,      // 客户端订单ID前缀到实例的路由
+    stale_timeouts: Arc<RwLock<HashMap<InstId, u64>>>, // 各实例的挂单过期时间，单位毫秒
 
-    decision: Mpsc<(Symbol, Signal)>,
+    decision: Mpsc<Decision>,
+    order_done: Mpsc<OrderId>,   // 已成交或已撤销的订单
+    stale: Mpsc<OrderId>,        // 超时未成交的挂单
+    prices: Mpsc<(Symbol, f64)>, // 最新成交价，用于止损
+
+    risk: Arc<RwLock<Risk>>, // 全局风控
+    shutdown: Shutdown,      // 退出时的挂单与持仓处理
+
+    control: Mpsc<Control>,       // 控制命令
+    control_path: Option<String>, // 控制接口的 Unix socket
+    metrics_addr: Option<String>, // Prometheus 指标地址
 
     principal: f64,
+
+    state_path: Option<String>,       // 状态日志文件
+    store: Option<Arc<Mutex<Store>>>, // 状态持久化
+
+    journal_path: Option<String>,         // 交易日志文件
+    journal: Option<Arc<Mutex<Journal>>>, // 交易日志
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct Order {
+    inst_id: InstId,
     symbol: String,
     id: u64,
+    #[serde(default)]
+    client_order_id: String,
+    #[serde(default)]
+    strategies: Vec<String>, // 触发下单的策略，止损等引擎发起的订单为空
+    side: OrderSide,
 
     quality: f64,
+    price: f64,
     buy_price: f64,
     min_sell_price: f64,
 
     status: OrderStatus,
+    #[serde(default)]
+    filled: Fill,
 
     update_ts: u64,
 }
 
+/// 订单的累计成交
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Fill {
+    quantity: f64,
+    avg_price: f64,
+    commission: f64,
+    commission_asset: String,
+    #[serde(default)]
+    commission_quote: Option<f64>, // 手续费折合计价资产，BNB 等其他资产在订单完成时按汇率折算
+    #[serde(default)]
+    pnl: Option<f64>, // 卖单的已实现盈亏，扣除买卖手续费
+    #[serde(default)]
+    last_trade_id: i64, // 已计入手续费的最新成交ID，重复推送的成交回报不再累加
+}
+
+impl Fill {
+    /// 累加一笔成交的手续费，成交ID不大于已计入的最新ID时视为重复推送，跳过
+    fn add_commission(&mut self, trade_id: i64, commission: f64, asset: Option<&str>) {
+        if trade_id <= self.last_trade_id {
+            return;
+        }
+        self.last_trade_id = trade_id;
+        self.commission += commission;
+        if let Some(asset) = asset {
+            self.commission_asset = asset.to_string();
+        }
+    }
+}
+
+impl Order {
+    /// 成交均价，尚无成交时取挂单价
+    fn fill_price(&self) -> f64 {
+        if self.filled.avg_price > 0. {
+            self.filled.avg_price
+        } else {
+            self.price
+        }
+    }
+
+    /// 成交金额，不含手续费
+    fn filled_amount(&self) -> f64 {
+        self.filled.quantity * self.fill_price()
+    }
+
+    /// 手续费以基础资产支付，已从得到的数量中扣除。没有手续费明细时按此估算
+    fn commission_in_base(&self) -> bool {
+        let base = split_symbol(&self.symbol).map(|(base, _)| base);
+        self.filled.commission_asset.is_empty()
+            || base == Some(self.filled.commission_asset.as_str())
+    }
+
+    /// 手续费以基础资产与计价资产以外的资产（如 BNB）支付时，折算所需的交易对
+    fn commission_rate_symbol(&self) -> Option<Symbol> {
+        let (base, quote) = split_symbol(&self.symbol)?;
+        let asset = self.filled.commission_asset.as_str();
+        if self.filled.commission <= 0. || asset.is_empty() || asset == base || asset == quote {
+            return None;
+        }
+        Some(format!("{}{}", asset, quote))
+    }
+
+    /// 手续费折合计价资产，没有手续费明细或未能折算时按默认费率估算
+    fn commission_value(&self) -> f64 {
+        if let Some(value) = self.filled.commission_quote {
+            return value;
+        }
+        let asset = Some(self.filled.commission_asset.as_str());
+        match split_symbol(&self.symbol) {
+            Some((_, quote)) if asset == Some(quote) => self.filled.commission,
+            Some((base, _)) if asset == Some(base) => self.filled.commission * self.fill_price(),
+            _ => self.filled_amount() * FEE_RATE,
+        }
+    }
+
+    /// 扣除手续费后实际得到的基础资产数量，没有手续费明细时按默认费率估算
+    fn received_quantity(&self) -> f64 {
+        let base = split_symbol(&self.symbol).map(|(base, _)| base);
+        if self.filled.commission_asset.is_empty() {
+            self.filled.quantity * (1. - FEE_RATE)
+        } else if base == Some(self.filled.commission_asset.as_str()) {
+            self.filled.quantity - self.filled.commission
+        } else {
+            self.filled.quantity
+        }
+    }
+
+    /// 卖出得到的计价资产，扣除折合计价资产的手续费
+    fn received_quote(&self) -> f64 {
+        self.filled_amount() - self.commission_value()
+    }
+
+    /// 用查询到的订单同步状态与累计成交，补上丢失的订单回报。查到的成交多于回报时手续费明细不全，
+    /// 改按默认费率估算。返回状态或成交是否有变化。
+    pub(crate) fn sync(&mut self, rest: &RestOrder) -> bool {
+        let status = OrderStatus::from(&rest.status);
+        let filled = rest.executed_qty > self.filled.quantity;
+        if filled {
+            self.filled.quantity = rest.executed_qty;
+            self.filled.avg_price = rest.cummulative_quote_qty / rest.executed_qty;
+            self.filled.commission = 0.;
+            self.filled.commission_asset.clear();
+            self.filled.commission_quote = None;
+        }
+        let changed = filled || status != self.status;
+        self.status = status;
+        self.update_ts = self.update_ts.max(rest.update_time);
+        changed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) enum OrderStatus {
+    /// 已提交，等待交易所确认
     Committed,
+    /// 已挂单
     Accepted,
+    /// 部分成交
+    PartiallyFilled,
+    /// 撤单中
+    PendingCancel,
+    /// 全部成交
     Success,
+    /// 已撤销，可能部分成交
     Canceled,
+    /// 交易所拒绝
+    Rejected,
+    /// 按订单类型规则或被交易所取消，可能部分成交
+    Expired,
+}
+
+impl OrderStatus {
+    /// 订单仍在交易所挂单中
+    pub(crate) fn is_open(&self) -> bool {
+        matches!(
+            self,
+            OrderStatus::Committed
+                | OrderStatus::Accepted
+                | OrderStatus::PartiallyFilled
+                | OrderStatus::PendingCancel
+        )
+    }
+}
+
+impl From<&RestOrderStatus> for OrderStatus {
+    fn from(status: &RestOrderStatus) -> Self {
+        match status {
+            RestOrderStatus::New => OrderStatus::Accepted,
+            // Trade 是成交回报的执行类型，不会作为订单状态出现
+            RestOrderStatus::PartiallyFilled | RestOrderStatus::Trade => {
+                OrderStatus::PartiallyFilled
+            }
+            RestOrderStatus::Filled => OrderStatus::Success,
+            RestOrderStatus::Canceled => OrderStatus::Canceled,
+            RestOrderStatus::PendingCancel => OrderStatus::PendingCancel,
+            RestOrderStatus::Rejected => OrderStatus::Rejected,
+            RestOrderStatus::Expired => OrderStatus::Expired,
+        }
+    }
 }
 
 pub struct State {
     instances: HashMap<InstId, Instance>,
     optimal_price: Arc<RwLock<HashMap<Symbol, Price>>>,
     profit: Arc<RwLock<f64>>,
-    principal: Arc<RwLock<f64>>,
+    capital: Arc<RwLock<Capital>>,
     orders: Arc<RwLock<HashMap<OrderId, Order>>>,
     archived_orders: Arc<RwLock<Vec<Order>>>,
 }
 
+/// 盈亏，手续费均折合计价资产
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pnl {
+    pub realized: f64,   // 已实现盈亏，扣除买卖手续费
+    pub unrealized: f64, // 持仓按最新价格计算的浮动盈亏，扣除买入手续费
+    pub commission: f64, // 累计手续费
+}
+
+impl Pnl {
+    fn merge(&mut self, other: &Pnl) {
+        self.realized += other.realized;
+        self.unrealized += other.unrealized;
+        self.commission += other.commission;
+    }
+}
+
+impl State {
+    /// 按交易对汇总各实例的盈亏
+    fn symbol_pnl(&self) -> BTreeMap<Symbol, Pnl> {
+        let mut pnl = BTreeMap::<Symbol, Pnl>::new();
+        for inst in self.instances.values() {
+            pnl.entry(inst.symbol.clone()).or_default().merge(&inst.pnl);
+        }
+        pnl
+    }
+}
+
+/// 退出时的持仓与累计收益
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub positions: Vec<PositionSummary>,
+    pub pnl: BTreeMap<Symbol, Pnl>, // 各交易对的盈亏
+    pub profit: f64,                // 累计已实现收益
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionSummary {
+    pub inst_id: String,
+    pub symbol: String,
+    pub quantity: f64,
+    pub buy_price: f64,
+    pub cost: f64,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.positions.is_empty() {
+            writeln!(f, "Positions: none")?;
+        } else {
+            writeln!(f, "Positions:")?;
+        }
+        for p in &self.positions {
+            writeln!(
+                f,
+                "  {} {}: quantity {}, buy price {}, cost {:.4}",
+                p.inst_id, p.symbol, p.quantity, p.buy_price, p.cost
+            )?;
+        }
+        for (symbol, pnl) in &self.pnl {
+            writeln!(
+                f,
+                "  {}: realized {:.4}, unrealized {:.4}, commission {:.4}",
+                symbol, pnl.realized, pnl.unrealized, pnl.commission
+            )?;
+        }
+        write!(f, "Realized profit: {:.4}", self.profit)
+    }
+}
+
+/// 交易对的最优报价，来自 book ticker 数据流
+#[derive(Debug, Clone, Copy)]
 pub struct Price {
-    buy: f64,
-    sell: f64,
+    pub(crate) buy: f64,  // 买一价
+    pub(crate) sell: f64, // 卖一价
 }
 
 impl Engine {
     pub fn new_with_env(config: Config) -> Self {
         let api_key = std::env::var("BQ_KEY").expect("read env var");
         let secret_key = std::env::var("BQ_SECRET").expect("read env var");
-        Self::new(&api_key, &secret_key, config)
+        let exchange = Exchanges::BinanceSpot(BinanceSpot::new(&api_key, &secret_key));
+        Self::new(exchange, config)
+    }
+
+    /// 模拟盘：订单在进程内撮合，计价资产取第一个实例的交易对。
+    pub fn new_paper(config: Config) -> Self {
+        let quote_asset = config
+            .instances
+            .first()
+            .and_then(|inst| split_symbol(&inst.symbol.to_uppercase()).map(|(_, q)| q.to_string()))
+            .unwrap_or_else(|| "USDT".to_string());
+        let exchange = Exchanges::Paper(Paper::new(&quote_asset, config.principal));
+        Self::new(exchange, config)
     }
 
-    pub fn new(api_key: &str, secret_key: &str, config: Config) -> Self {
-        tracing::info!(api_key, secret_key);
-        // 初始化账户
-        let account = Account::new(Some(api_key.to_string()), Some(secret_key.to_string()));
-        let user_stream = UserStream::new(Some(api_key.to_string()), Some(secret_key.to_string()));
+    pub fn new(exchange: Exchanges, config: Config) -> Self {
+        if let Err(e) = check_instances(&config) {
+            tracing::error!("Invalid config, {}", e);
+            panic!("{}", e);
+        }
+        // 模拟盘的撮合状态只在内存中，重启后无从对账，不恢复引擎状态；交易日志照常记录，用于评估策略
+        let state_path = match exchange {
+            Exchanges::Paper(_) => None,
+            _ => Some(config.state_path.clone()),
+        };
+        let journal_path = Some(config.journal_path.clone()).filter(|path| !path.is_empty());
 
         // 数据通道
         let mut data_channels: HashMap<DataChannelIndex, Broadcast<Data>> = HashMap::new();
-        let mut symbols = HashSet::new();
         let mut streams = HashSet::new();
         let mut instances = HashMap::new();
-        let mut order_channel = Mpsc::default();
+        let order_channel = Mpsc::default();
 
-        for inst_conf in config.instances {
-            let mut strategies = Vec::new();
-            for strategy in inst_conf.strategies {
-                let (strategy, interval) = match strategy {
-                    config::Strategy::Rsi {
-                        id,
-                        strategy_type,
-                        interval,
-                        period,
-                        buy_threshold,
-                        sell_threshold,
-                    } => (
-                        Strategies::RelativeStrengthIndex(RelativeStrengthIndex::new(
-                            period,
-                            interval.clone(),
-                            buy_threshold,
-                            sell_threshold,
-                        )),
-                        interval,
-                    ),
-                    config::Strategy::Atr {
-                        id,
-                        strategy_type,
-                        interval,
-                        period,
-                        threshold,
-                    } => (
-                        Strategies::AverageTrueRange(AverageTrueRange::new(
-                            period,
-                            interval.clone(),
-                        )),
-                        interval,
-                    ),
-                };
-                data_channels.insert(
-                    (inst_conf.symbol.to_uppercase(), &strategy).data_index(),
-                    Broadcast::<Data>::default(),
-                );
-
-                strategies.push(strategy);
-                streams.insert(kline_stream(&inst_conf.symbol, &interval.to_string()));
+        // 资金分配：实例本金之和超过全局本金时拒绝启动
+        let mut capital = Capital::new(config.principal);
+        for inst_conf in &config.instances {
+            if let Err(e) = capital.reserve(&inst_conf.id, inst_conf.principal) {
+                tracing::error!("Allocate capital failed, {}", e);
+                panic!("{}", e);
             }
-
-            // streams.insert(book_ticker_stream(&inst_conf.symbol));
-
-            instances.push(
-                inst_conf.id,
-                Instance::new(
-                    &inst_conf.symbol.to_uppercase(),
-                    inst_conf.mode.clone(),
-                    strategies,
-                ),
-            );
-
-            data_channels.insert(
-                (inst_conf.symbol.to_uppercase(), Category::BookTicker).data_index(),
-                Broadcast::<Data>::default(),
-            );
-
-            symbols.insert(inst_conf.symbol.to_uppercase());
         }
-
-        let data_channels_cloned = data_channels
+        let capital = Arc::new(RwLock::new(capital));
+        let inst_symbols = config
+            .instances
             .iter()
-            .map(|e| (e.0.clone(), e.1.clone()))
-            .collect::<HashMap<_, _>>();
+            .map(|inst| (inst.id.clone(), inst.symbol.to_uppercase()))
+            .collect();
+        let risk = Arc::new(RwLock::new(Risk::new(
+            config.risk.clone(),
+            config.principal,
+            inst_symbols,
+        )));
+        let optimal_price: Arc<RwLock<HashMap<Symbol, Price>>> = Default::default();
+        let order_done: Mpsc<OrderId> = Default::default();
 
-        let order_tx = order_channel.tx.clone();
-
-        let wss = WebSockets::new(move |e: CombinedStreamEvent<WebsocketEventUntag>| {
-            match e.data {
-                WebsocketEventUntag::WebsocketEvent(WebsocketEvent::OrderUpdate(order)) => {
-                    match order_tx.send(*order) {
-                        Ok(()) => {
-                            tracing::info!("Send OrderUpdate Ok");
-                        }
-                        Err(e) => {
-                            tracing::error!("Send OrderUpdate failed: {:?}", e);
-                        }
-                    }
-                }
-                WebsocketEventUntag::WebsocketEvent(WebsocketEvent::Kline(kline)) => {
-                    let mut data_tx = data_channels_cloned.get(&kline.data_index()).unwrap();
-                    match data_tx.tx.send(Data::Kline(*kline)) {
-                        Ok(size) => {
-                            tracing::info!("Send Kline, size: {:?}", size);
-                        }
-                        Err(e) => {
-                            tracing::error!("Send Kline failed,{:?}", e);
-                        }
-                    }
-                }
-                WebsocketEventUntag::BookTicker(bt) => {
-                    let mut data_tx = data_channels_cloned.get(&bt.data_index()).unwrap();
-                    match data_tx.tx.send(Data::BookTicker(*bt)) {
-                        Ok(size) => {
-                            tracing::info!("Send BookTicker, size: {:?}", size);
-                        }
-                        Err(e) => {
-                            tracing::error!("Send BookTicker failed,{:?}", e);
-                        }
-                    }
-                }
-                _ => {}
+        for inst_conf in &config.instances {
+            for (index, stream) in subscriptions(inst_conf) {
+                data_channels.entry(index).or_default();
+                streams.insert(stream);
             }
-            Ok(())
-        });
+            instances.insert(
+                inst_conf.id.clone(),
+                build_instance(inst_conf, &capital, &optimal_price, &risk, &order_done.tx),
+            );
+        }
+        let inst_configs = config
+            .instances
+            .into_iter()
+            .map(|inst_conf| (inst_conf.id.clone(), inst_conf))
+            .collect();
 
         Self {
-            account,
-            wss_streams: streams.into_iter().collect::<Vec<_>>(),
-            wss: Some(wss),
+            exchange: Arc::new(exchange),
+            wss_streams: Arc::new(RwLock::new(streams.into_iter().collect())),
             state: State {
-                instances: instances,
-                optimal_price: Default::default(),
+                instances,
+                optimal_price,
                 profit: Default::default(),
-                principal: Arc::new(RwLock::new(config.principal)),
+                capital,
                 orders: Default::default(),
                 archived_orders: Default::default(),
             },
-            data_channels,
+            data_channels: Arc::new(Mutex::new(data_channels)),
+            data_gap: Default::default(),
+            reconnected: Default::default(),
+            stop: Default::default(),
+            resubscribe: Default::default(),
+            reload: Default::default(),
+            inst_configs,
+            routes: Default::default(),
+            stale_timeouts: Default::default(),
             decision: Default::default(),
+            order_done,
+            stale: Default::default(),
+            prices: Default::default(),
+            risk,
+            shutdown: config.shutdown,
+            control: Default::default(),
+            control_path: Some(config.control_path).filter(|path| !path.is_empty()),
+            metrics_addr: Some(config.metrics_addr).filter(|addr| !addr.is_empty()),
             principal: config.principal,
-            user_stream,
-            order_channel: order_channel,
+            order_channel,
+            state_path,
+            store: None,
+            journal_path,
+            journal: None,
         }
     }
 
-    pub async fn run(&mut self) {
-        // self.run_best_price();
+    /// 热加载配置的发送端，引擎运行中收到新配置后校验并应用。
+    pub fn reloader(&self) -> UnboundedSender<Config> {
+        self.reload.tx.clone()
+    }
+
+    /// 启动引擎，`shutdown` 完成后有序退出并返回持仓与收益汇总。
+    pub async fn run(&mut self, shutdown: impl Future<Output = ()>) -> Summary {
+        // Track best bid/ask
+        let symbols = self
+            .state
+            .instances
+            .values()
+            .map(|inst| inst.symbol.clone())
+            .collect();
+        self.run_best_price(symbols);
+        // Load symbol trading rules
+        self.load_filters().await;
+        // Open the trade journal
+        self.open_journal();
+        // Restore state from the journal
+        self.restore().await;
+        // Restore orders and instance states from the exchange
+        self.reconcile().await;
+        // Warm up strategies with historical klines
+        self.warm_up().await;
         // start wss
         self.run_wss().await;
         // Run instance
         self.run_instances().await;
         // Handle order
+        self.update_routes().await;
         self.run_order_monitor();
+        // Watch prices for stop loss
+        let channels = self
+            .data_channels
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        self.run_price_monitor(channels);
+        // Report request rate usage
+        self.run_rate_monitor();
+        // Serve control commands
+        self.run_control();
+        // Export metrics
+        self.run_metrics().await;
         // Handle trade signal
-        self.run_trade_handle().await;
+        let order_done_rx = self.run_trade_handle(shutdown).await;
+        // Shut down
+        self.stop(order_done_rx).await
+    }
+
+    // 有序退出：按配置撤单与平仓，等待订单回报结算后停止实例与数据流，保存状态
+    async fn stop(&mut self, mut order_done_rx: UnboundedReceiver<OrderId>) -> Summary {
+        tracing::info!("Engine stopping, {:?}", self.shutdown);
+        if self.shutdown.cancel_orders || self.shutdown.flatten {
+            for instance in self.state.instances.values_mut() {
+                instance.cancel(&self.exchange, &self.state.orders).await;
+                persist_instance(&self.store, instance, &self.state.orders).await;
+            }
+            self.wait_orders(&mut order_done_rx).await;
+        }
+        if self.shutdown.flatten {
+            for instance in self.state.instances.values_mut() {
+                instance.flatten(&self.exchange, &self.state.orders).await;
+                persist_instance(&self.store, instance, &self.state.orders).await;
+            }
+            self.wait_orders(&mut order_done_rx).await;
+        }
+
+        for instance in self.state.instances.values_mut() {
+            instance.stop();
+        }
+        let _ = self.stop.tx.send(());
+        self.compact_store().await;
+        if let Some(path) = &self.control_path {
+            let _ = std::fs::remove_file(path);
+        }
+
+        let summary = self.summary().await;
+        tracing::info!("Engine stopped, {:?}", summary);
+        summary
+    }
+
+    // 等待各实例的挂单结算，超时后放弃，剩余挂单在重启后对账恢复
+    async fn wait_orders(&mut self, order_done_rx: &mut UnboundedReceiver<OrderId>) {
+        let deadline = time::sleep(SHUTDOWN_WAIT);
+        tokio::pin!(deadline);
+        while self
+            .state
+            .instances
+            .values()
+            .any(|inst| inst.order_id.is_some() || !inst.revoked.is_empty())
+        {
+            tokio::select! {
+                Some(order_id) = order_done_rx.recv() => {
+                    self.on_order_done(&order_id).await;
+                }
+                _ = &mut deadline => {
+                    tracing::error!("Wait orders timeout");
+                    return;
+                }
+            }
+        }
+    }
+
+    // 当前持仓与累计收益
+    async fn summary(&self) -> Summary {
+        let mut positions = self
+            .state
+            .instances
+            .values()
+            .filter_map(|inst| {
+                inst.position.as_ref().map(|p| PositionSummary {
+                    inst_id: inst.id.clone(),
+                    symbol: inst.symbol.clone(),
+                    quantity: p.quantity,
+                    buy_price: p.buy_price,
+                    cost: p.cost,
+                })
+            })
+            .collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.inst_id.cmp(&b.inst_id));
+        Summary {
+            positions,
+            pnl: self.state.symbol_pnl(),
+            profit: *self.state.profit.read().await,
+        }
+    }
+
+    // 启动前与交易所对账，重建订单与实例状态
+    async fn reconcile(&mut self) {
+        for instance in self.state.instances.values_mut() {
+            if let Err(e) = instance.reconcile(&self.exchange, &self.state.orders).await {
+                tracing::error!("Reconcile instance {} failed, {:?}", instance.id, e);
+                panic!("{:?}", e);
+            }
+        }
+        self.compact_store().await;
+    }
+
+    // 用历史K线预热策略，此时交易处理尚未启动，不会下单
+    async fn warm_up(&self) {
+        for instance in self.state.instances.values() {
+            if let Err(e) = instance.warm_up(&self.exchange).await {
+                tracing::error!("Warm up instance {} failed, {:?}", instance.id, e);
+                panic!("{:?}", e);
+            }
+        }
+    }
+
+    // 加载各交易对的下单规则
+    async fn load_filters(&mut self) {
+        let info = match self.exchange.exchange_info().await {
+            Ok(info) => info,
+            Err(e) => {
+                tracing::error!("Load exchange info failed, {:?}", e);
+                panic!("{:?}", e);
+            }
+        };
+        for instance in self.state.instances.values_mut() {
+            let filter = match info.symbols.iter().find(|s| s.symbol == instance.symbol) {
+                Some(symbol) => SymbolFilter::try_from(symbol),
+                None => Err(format!("Unknown symbol {}", instance.symbol)),
+            };
+            match filter {
+                Ok(filter) => instance.filter = filter,
+                Err(e) => {
+                    tracing::error!("Load filters failed, {}", e);
+                    panic!("{}", e);
+                }
+            }
+        }
+    }
+
+    // 打开交易日志，之后的决策、订单与成交追加到日志
+    fn open_journal(&mut self) {
+        let Some(path) = &self.journal_path else {
+            return;
+        };
+        match Journal::open(path) {
+            Ok(journal) => {
+                tracing::info!("Trade journal opened, {}", path);
+                self.journal = Some(Arc::new(Mutex::new(journal)));
+            }
+            Err(e) => {
+                tracing::error!("Open trade journal failed, {:?}", e);
+                panic!("{:?}", e);
+            }
+        }
+    }
+
+    // 回放状态日志，恢复订单、收益与实例状态
+    async fn restore(&mut self) {
+        let Some(path) = &self.state_path else {
+            return;
+        };
+        let (store, snapshot) = match Store::open(path) {
+            Ok(store) => store,
+            Err(e) => {
+                tracing::error!("Open state journal failed, {:?}", e);
+                panic!("{:?}", e);
+            }
+        };
+        tracing::info!(
+            "State restored, orders: {}, archived: {}",
+            snapshot.orders.len(),
+            snapshot.archived_orders.len()
+        );
+        *self.state.orders.write().await = snapshot.orders;
+        *self.state.archived_orders.write().await = snapshot.archived_orders;
+        *self.state.profit.write().await = snapshot.profit;
+        self.risk
+            .write()
+            .await
+            .restore(snapshot.profit, snapshot.risk);
+        for (id, inst) in snapshot.instances {
+            if let Some(instance) = self.state.instances.get_mut(&id) {
+                instance.restore(inst).await;
+            }
+        }
+        self.store = Some(Arc::new(Mutex::new(store)));
+    }
+
+    // 对账后的完整状态写回日志
+    async fn compact_store(&self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let mut instances = HashMap::new();
+        for instance in self.state.instances.values() {
+            instances.insert(instance.id.clone(), instance.snapshot().await);
+        }
+        let snapshot = Snapshot {
+            orders: self.state.orders.read().await.clone(),
+            archived_orders: self.state.archived_orders.read().await.clone(),
+            profit: *self.state.profit.read().await,
+            instances,
+            risk: self.risk.read().await.state(),
+        };
+        if let Err(e) = store.lock().unwrap().compact(snapshot) {
+            tracing::error!("Compact state journal failed, {:?}", e);
+        }
     }
 
     // 启动各个实例
     async fn run_instances(&mut self) {
+        let data_channels = self.data_channels.lock().unwrap().clone();
         let instances = &mut self.state.instances;
-        for instance in instances.iter_mut() {
-            instance.run(&self.data_channels).await;
+        for instance in instances.values_mut() {
+            instance
+                .run(
+                    &self.exchange,
+                    &data_channels,
+                    &self.data_gap,
+                    self.decision.tx.clone(),
+                )
+                .await;
         }
     }
 
     // 启动wss数据流
     async fn run_wss(&mut self) {
-        let keep_running = AtomicBool::new(true);
-        // 监听数据流
-        let mut streams = self.wss_streams.clone();
+        let channels = Channels {
+            data: self.data_channels.clone(),
+            order: self.order_channel.tx.clone(),
+            gap: self.data_gap.clone(),
+            reconnected: self.reconnected.clone(),
+            resubscribe: self.resubscribe.clone(),
+            stop: self.stop.clone(),
+        };
+        if let Err(e) = self
+            .exchange
+            .subscribe(self.wss_streams.clone(), channels)
+            .await
+        {
+            tracing::error!("Subscribe streams failed, {:?}", e);
+            panic!("{:?}", e);
+        }
+    }
+
+    // 交易处理：WaitBuy 收到买入决策时下买单，WaitSell 收到卖出决策时下卖单，订单完成后推进实例状态。
+    // 收到退出信号后返回订单完成通道，用于退出时等待订单结算。
+    async fn run_trade_handle(
+        &mut self,
+        shutdown: impl Future<Output = ()>,
+    ) -> UnboundedReceiver<OrderId> {
+        let mut decision_rx = self
+            .decision
+            .rx
+            .take()
+            .expect("decision receiver already taken");
+        let mut order_done_rx = self
+            .order_done
+            .rx
+            .take()
+            .expect("order_done receiver already taken");
+        let mut prices_rx = self
+            .prices
+            .rx
+            .take()
+            .expect("prices receiver already taken");
+        let mut stale_rx = self.stale.rx.take().expect("stale receiver already taken");
+        let mut control_rx = self
+            .control
+            .rx
+            .take()
+            .expect("control receiver already taken");
+        let mut reload_rx = self
+            .reload
+            .rx
+            .take()
+            .expect("reload receiver already taken");
+        let mut reconnected_rx = self.reconnected.tx.subscribe();
+        let journal = self.journal.clone();
+        let exchange = self.exchange.clone();
+        let orders = self.state.orders.clone();
+        let risk = self.risk.clone();
+        let store = self.store.clone();
+        let mut halted = false;
+        tokio::pin!(shutdown);
+        tracing::info!("Trade handle started");
+        loop {
+            // 风控停止交易后撤销买单，之后只处理卖出、订单回报与止损
+            if !halted {
+                let reason = risk.read().await.halted().map(str::to_string);
+                if let Some(reason) = reason {
+                    tracing::error!("Stop all instances, {}", reason);
+                    halted = true;
+                    for instance in self.state.instances.values_mut() {
+                        instance.halt(&exchange, &orders).await;
+                        persist_instance(&store, instance, &orders).await;
+                    }
+                }
+            }
+            tokio::select! {
+                Some(decision) = decision_rx.recv() => {
+                    let entry = self
+                        .state
+                        .instances
+                        .get(&decision.inst_id)
+                        .and_then(|inst| Entry::decision(&decision, &inst.symbol));
+                    if let Some(entry) = entry {
+                        record(&journal, &entry);
+                    }
+                    tracing::info!("Trade decision, {:?}", decision);
+                    let Some(instance) = self.state.instances.get_mut(&decision.inst_id) else {
+                        continue;
+                    };
+                    if instance.on_decision(decision, halted, &exchange, &orders).await {
+                        persist_instance(&store, instance, &orders).await;
+                    }
+                }
+                Some(order_id) = order_done_rx.recv() => {
+                    self.on_order_done(&order_id).await;
+                }
+                Some(order_id) = stale_rx.recv() => {
+                    let Some(order) = orders.read().await.get(&order_id).cloned() else {
+                        continue;
+                    };
+                    // 处理前订单可能已成交或已撤销
+                    if !matches!(
+                        order.status,
+                        OrderStatus::Committed | OrderStatus::Accepted | OrderStatus::PartiallyFilled
+                    ) {
+                        continue;
+                    }
+                    if let Some(instance) = self.state.instances.get_mut(&order.inst_id) {
+                        // 停止交易后只处理持仓的卖单
+                        if halted && instance.position.is_none() {
+                            continue;
+                        }
+                        instance.on_order_stale(&order, &exchange, &orders).await;
+                        if let Some(order) = orders.read().await.get(&order_id) {
+                            persist(&store, &Record::Order(order.clone()));
+                        }
+                        persist_instance(&store, instance, &orders).await;
+                    }
+                }
+                Some((symbol, price)) = prices_rx.recv() => {
+                    for instance in self.state.instances.values_mut() {
+                        if instance.symbol == symbol {
+                            let unrealized = instance.mark(price);
+                            METRICS
+                                .unrealized_pnl
+                                .with_label_values(&[instance.id.as_str()])
+                                .set(unrealized);
+                            let stopping = instance.stopping;
+                            instance.check_stop_loss(&exchange, &orders, price).await;
+                            if instance.stopping != stopping {
+                                persist_instance(&store, instance, &orders).await;
+                            }
+                        }
+                    }
+                }
+                Some(config) = reload_rx.recv() => {
+                    self.reload(config).await;
+                }
+                Ok(()) = reconnected_rx.recv() => {
+                    self.sync_orders().await;
+                }
+                Some((command, reply_tx)) = control_rx.recv() => {
+                    let reply = self.on_command(command).await;
+                    let _ = reply_tx.send(reply);
+                    if halted && risk.read().await.halted().is_none() {
+                        tracing::info!("Resume all instances");
+                        halted = false;
+                    }
+                }
+                _ = &mut shutdown => {
+                    tracing::info!("Shutdown signal received");
+                    break;
+                }
+                else => break,
+            }
+        }
+        order_done_rx
+    }
 
-        match self.user_stream.start().await {
-            Ok(resp) => {
-                streams.push(resp.listen_key);
-                tracing::info!("Join user stream");
+    // 重连期间的订单回报不会重发，逐个查询未完成的订单，已完成的按订单完成处理
+    async fn sync_orders(&mut self) {
+        let open = self
+            .state
+            .orders
+            .read()
+            .await
+            .values()
+            .filter(|o| o.status.is_open())
+            .map(|o| (o.id, o.symbol.clone()))
+            .collect::<Vec<_>>();
+        tracing::info!("Sync {} open orders after reconnect", open.len());
+        for (id, symbol) in open {
+            let request = OrderStatusRequest {
+                symbol,
+                order_id: Some(id),
+                ..Default::default()
+            };
+            let rest = match self.exchange.order_status(request).await {
+                Ok(rest) => rest,
+                Err(e) => {
+                    tracing::error!("Sync order {} failed, {:?}", id, e);
+                    continue;
+                }
+            };
+            let order_id = id.to_string();
+            let done = {
+                let mut orders = self.state.orders.write().await;
+                let Some(order) = orders.get_mut(&order_id) else {
+                    continue;
+                };
+                if !order.sync(&rest) {
+                    continue;
+                }
+                persist(&self.store, &Record::Order(order.clone()));
+                !order.status.is_open()
+            };
+            if done {
+                tracing::info!("Order {} completed during the outage", order_id);
+                self.on_order_done(&order_id).await;
+            }
+        }
+    }
+
+    // 订单完成后推进实例状态，记录已实现盈亏
+    async fn on_order_done(&mut self, order_id: &OrderId) {
+        let orders = &self.state.orders;
+        let Some(mut order) = orders.read().await.get(order_id).cloned() else {
+            return;
+        };
+        // 以其他资产支付的手续费按当前汇率折合计价资产
+        if let Some(symbol) = order.commission_rate_symbol() {
+            match self.rate(&symbol).await {
+                Ok(rate) => {
+                    order.filled.commission_quote = Some(order.filled.commission * rate);
+                    update_order(&self.store, orders, &order).await;
+                }
+                Err(e) => {
+                    tracing::error!("Value commission of order {} failed, {:?}", order_id, e);
+                }
             }
+        }
+        let Some(instance) = self.state.instances.get_mut(&order.inst_id) else {
+            return;
+        };
+        let pnl = instance.on_order_done(&order, &self.exchange, orders).await;
+        persist_instance(&self.store, instance, orders).await;
+        if let Some(pnl) = pnl {
+            order.filled.pnl = Some(pnl);
+            update_order(&self.store, orders, &order).await;
+        }
+        record(&self.journal, &Entry::order(&order));
+        if let Some(pnl) = pnl {
+            let total = {
+                let mut profit = self.state.profit.write().await;
+                *profit += pnl;
+                *profit
+            };
+            tracing::info!("Realized pnl {}, total profit {}", pnl, total);
+            METRICS
+                .realized_pnl
+                .with_label_values(&[order.inst_id.as_str()])
+                .set(instance.pnl.realized);
+            persist(&self.store, &Record::Profit(total));
+            let risk = {
+                let mut risk = self.risk.write().await;
+                risk.record_pnl(pnl, now());
+                risk.state()
+            };
+            persist(&self.store, &Record::Risk(risk));
+        }
+    }
+
+    // 交易对的当前价格，优先取最优报价的中间价
+    async fn rate(&self, symbol: &str) -> binance::errors::Result<f64> {
+        if let Some(price) = self.state.optimal_price.read().await.get(symbol) {
+            return Ok((price.buy + price.sell) / 2.);
+        }
+        self.exchange.price(symbol).await
+    }
+
+    // 最优报价：从 book ticker 数据流维护各交易对的买一价与卖一价
+    fn run_best_price(&self, symbols: HashSet<Symbol>) {
+        for symbol in symbols {
+            let Some(mut data_rx) = self
+                .data_channels
+                .lock()
+                .unwrap()
+                .get(&(symbol, Category::BookTicker).data_index())
+                .map(|data_tx| data_tx.tx.subscribe())
+            else {
+                continue;
+            };
+            let optimal_price = self.state.optimal_price.clone();
+            tokio::spawn(async move {
+                loop {
+                    match data_rx.recv().await {
+                        Ok(Data::BookTicker(b)) => {
+                            let price = Price {
+                                buy: b.best_bid,
+                                sell: b.best_ask,
+                            };
+                            optimal_price.write().await.insert(b.symbol, price);
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(n)) => {
+                            METRICS
+                                .broadcast_lagged
+                                .with_label_values(&["best_price"])
+                                .inc_by(n);
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+        }
+    }
+
+    // 价格监控：从行情数据中取最新价格转发给交易处理
+    fn run_price_monitor(&self, channels: Vec<Broadcast<Data>>) {
+        for data_tx in channels {
+            let mut data_rx = data_tx.tx.subscribe();
+            let prices_tx = self.prices.tx.clone();
+            tokio::spawn(async move {
+                loop {
+                    let price = match data_rx.recv().await {
+                        Ok(Data::Kline(k)) => (k.symbol, k.kline.close),
+                        Ok(Data::BookTicker(b)) => (b.symbol, (b.best_bid + b.best_ask) / 2.),
+                        Err(RecvError::Lagged(n)) => {
+                            METRICS
+                                .broadcast_lagged
+                                .with_label_values(&["price_monitor"])
+                                .inc_by(n);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+                    if prices_tx.send(price).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+
+    // 更新订单监控使用的实例信息：客户端订单ID前缀与挂单过期时间
+    async fn update_routes(&self) {
+        let instances = self.state.instances.values();
+        *self.routes.write().await = instances
+            .clone()
+            .map(|inst| (inst.client_order_prefix(), inst.id.clone()))
+            .collect();
+        *self.stale_timeouts.write().await = instances
+            .map(|inst| (inst.id.clone(), inst.stale_order.timeout * 1000))
+            .collect();
+    }
+
+    // 热加载配置：校验通过后增删实例与数据通道、原地更新实例参数，校验失败时不改动运行状态
+    async fn reload(&mut self, config: Config) {
+        tracing::info!("Reload config");
+        let mut filters = match self.check_reload(&config).await {
+            Ok(filters) => filters,
             Err(e) => {
-                tracing::error!("Join user stream failed, {:?}", e);
-                panic!("{:?}", e);
+                tracing::error!("Reload config rejected, {}", e);
+                return;
             }
+        };
+        let budgets = config
+            .instances
+            .iter()
+            .map(|inst| (inst.id.clone(), inst.principal))
+            .collect();
+        if let Err(e) = self
+            .state
+            .capital
+            .write()
+            .await
+            .reallocate(config.principal, &budgets)
+        {
+            tracing::error!("Reload config rejected, {}", e);
+            return;
+        }
+        let inst_symbols = config
+            .instances
+            .iter()
+            .map(|inst| (inst.id.clone(), inst.symbol.to_uppercase()))
+            .collect();
+        self.risk.write().await.reconfigure(
+            config.risk.clone(),
+            config.principal - self.principal,
+            inst_symbols,
+        );
+        self.principal = config.principal;
+        self.shutdown = config.shutdown.clone();
+        if self
+            .state_path
+            .as_ref()
+            .is_some_and(|path| *path != config.state_path)
+            || self.control_path.as_deref().unwrap_or_default() != config.control_path
+            || self.metrics_addr.as_deref().unwrap_or_default() != config.metrics_addr
+            || self
+                .journal_path
+                .as_ref()
+                .is_some_and(|path| *path != config.journal_path)
+        {
+            tracing::info!(
+                "Changes of state_path, control_path, metrics_addr and journal_path take effect after restart"
+            );
         }
 
-        tracing::info!("Wss subscribed, {:?}", streams);
+        // 先补齐数据通道，新实例与重建的策略启动时订阅
+        let mut needed = HashSet::new();
+        let mut streams = HashSet::new();
+        for inst_conf in &config.instances {
+            for (index, stream) in subscriptions(inst_conf) {
+                needed.insert(index);
+                streams.insert(stream);
+            }
+        }
+        let added = {
+            let mut data_channels = self.data_channels.lock().unwrap();
+            let mut added = Vec::new();
+            for index in &needed {
+                if !data_channels.contains_key(index) {
+                    let tx = Broadcast::default();
+                    data_channels.insert(index.clone(), tx.clone());
+                    added.push(tx);
+                }
+            }
+            added
+        };
+        self.run_price_monitor(added);
+        let symbols = config
+            .instances
+            .iter()
+            .map(|inst| inst.symbol.to_uppercase())
+            .filter(|symbol| {
+                self.state
+                    .instances
+                    .values()
+                    .all(|inst| inst.symbol != *symbol)
+            })
+            .collect();
+        self.run_best_price(symbols);
 
-        tokio::spawn({
-            let mut wss = self.wss.take().unwrap();
+        // 移除的实例已校验过没有挂单与持仓
+        let ids = config
+            .instances
+            .iter()
+            .map(|inst| inst.id.clone())
+            .collect::<HashSet<_>>();
+        let removed = self
+            .state
+            .instances
+            .keys()
+            .filter(|id| !ids.contains(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+        for id in removed {
+            if let Some(mut instance) = self.state.instances.remove(&id) {
+                instance.stop();
+                METRICS.remove_instance(&id);
+                tracing::info!("Instance {} removed", id);
+            }
+            self.inst_configs.remove(&id);
+        }
 
-            async move {
-                match wss.connect_multiple(streams).await {
-                    Ok(()) => {
-                        tracing::info!("Wss connected");
+        let data_channels = self.data_channels.lock().unwrap().clone();
+        for inst_conf in config.instances {
+            match self.state.instances.get_mut(&inst_conf.id) {
+                Some(instance) => {
+                    let Some(old) = self.inst_configs.get(&inst_conf.id) else {
+                        continue;
+                    };
+                    if *old == inst_conf {
+                        continue;
                     }
-                    Err(e) => {
-                        tracing::error!("Wss connect failed, {:?}", e);
-                        panic!("{:?}", e);
+                    instance.stop();
+                    let fresh = instance.reconfigure(&inst_conf, old).await;
+                    if let Err(e) = instance.warm_up_strategies(&self.exchange, &fresh).await {
+                        tracing::error!("Warm up instance {} failed, {:?}", instance.id, e);
                     }
+                    instance
+                        .run(
+                            &self.exchange,
+                            &data_channels,
+                            &self.data_gap,
+                            self.decision.tx.clone(),
+                        )
+                        .await;
                 }
-                if let Err(e) = wss.event_loop(&keep_running).await {
-                    tracing::error!("Wss stopped, {:?}", e);
+                None => {
+                    let mut instance = build_instance(
+                        &inst_conf,
+                        &self.state.capital,
+                        &self.state.optimal_price,
+                        &self.risk,
+                        &self.order_done.tx,
+                    );
+                    if let Some(filter) = filters.remove(&inst_conf.id) {
+                        instance.filter = filter;
+                    }
+                    if let Err(e) = instance.warm_up(&self.exchange).await {
+                        tracing::error!("Warm up instance {} failed, {:?}", instance.id, e);
+                    }
+                    instance
+                        .run(
+                            &self.exchange,
+                            &data_channels,
+                            &self.data_gap,
+                            self.decision.tx.clone(),
+                        )
+                        .await;
+                    tracing::info!("Instance {} added", instance.id);
+                    self.state.instances.insert(inst_conf.id.clone(), instance);
                 }
             }
-        });
+            self.inst_configs.insert(inst_conf.id.clone(), inst_conf);
+        }
+
+        // 移除不再使用的数据通道，数据流变化后重新订阅
+        self.data_channels
+            .lock()
+            .unwrap()
+            .retain(|index, _| needed.contains(index));
+        let resubscribe = {
+            let mut wss_streams = self.wss_streams.write().await;
+            if wss_streams.iter().cloned().collect::<HashSet<_>>() != streams {
+                *wss_streams = streams.into_iter().collect();
+                true
+            } else {
+                false
+            }
+        };
+        if resubscribe {
+            let _ = self.resubscribe.tx.send(());
+        }
+
+        self.update_routes().await;
+        self.compact_store().await;
+        tracing::info!("Config reloaded");
     }
 
-    // 交易处理
-    async fn run_trade_handle(&mut self) {
-        let mut decision_tx = unsafe { self.decision.rx.take().unwrap_unchecked() };
-        tracing::info!("Trade handle started");
-        loop {
-            match decision_tx.recv().await {
-                Some((symbol, signal)) => {}
-                None => {}
+    // 校验新配置，返回新增实例的下单规则
+    async fn check_reload(&self, config: &Config) -> Result<HashMap<InstId, SymbolFilter>, String> {
+        check_instances(config)?;
+        let ids = config
+            .instances
+            .iter()
+            .map(|inst| inst.id.as_str())
+            .collect::<HashSet<_>>();
+        for inst_conf in &config.instances {
+            if let Some(instance) = self.state.instances.get(&inst_conf.id) {
+                if instance.symbol != inst_conf.symbol.to_uppercase() {
+                    return Err(format!(
+                        "Instance {} symbol changed from {} to {}",
+                        instance.id, instance.symbol, inst_conf.symbol
+                    ));
+                }
+            }
+        }
+        for instance in self.state.instances.values() {
+            if !ids.contains(instance.id.as_str())
+                && (instance.order_id.is_some() || instance.position.is_some())
+            {
+                return Err(format!(
+                    "Instance {} has an open order or position, close it before removing",
+                    instance.id
+                ));
+            }
+        }
+        let allocated = config
+            .instances
+            .iter()
+            .map(|inst| inst.principal)
+            .sum::<f64>();
+        if allocated > config.principal {
+            return Err(CapitalError::OverAllocated {
+                allocated,
+                principal: config.principal,
+            }
+            .to_string());
+        }
+
+        let mut filters = HashMap::new();
+        let added = config
+            .instances
+            .iter()
+            .filter(|inst| !self.state.instances.contains_key(&inst.id))
+            .collect::<Vec<_>>();
+        if added.is_empty() {
+            return Ok(filters);
+        }
+        let info = self
+            .exchange
+            .exchange_info()
+            .await
+            .map_err(|e| format!("Load exchange info failed, {:?}", e))?;
+        for inst_conf in added {
+            let symbol = inst_conf.symbol.to_uppercase();
+            let Some(s) = info.symbols.iter().find(|s| s.symbol == symbol) else {
+                return Err(format!("Unknown symbol {}", symbol));
             };
+            filters.insert(inst_conf.id.clone(), SymbolFilter::try_from(s)?);
+        }
+        Ok(filters)
+    }
+
+    // 启动控制接口
+    fn run_control(&self) {
+        let Some(path) = &self.control_path else {
+            return;
+        };
+        if let Err(e) = control::serve(path, self.control.tx.clone()) {
+            tracing::error!("Start control server failed, {:?}", e);
+            panic!("{:?}", e);
+        }
+    }
+
+    // 启动指标导出
+    async fn run_metrics(&self) {
+        let Some(addr) = &self.metrics_addr else {
+            return;
+        };
+        if let Err(e) = metrics::serve(addr).await {
+            tracing::error!("Start metrics server failed, {:?}", e);
+            panic!("{:?}", e);
+        }
+    }
+
+    // 执行控制命令
+    async fn on_command(&mut self, command: Command) -> Reply {
+        match command {
+            Command::Status => {
+                let mut instances = Vec::new();
+                for inst in self.state.instances.values() {
+                    let position = inst.position.as_ref();
+                    instances.push(InstanceStatus {
+                        id: inst.id.clone(),
+                        symbol: inst.symbol.clone(),
+                        state: *inst.state.read().await,
+                        paused: inst.paused,
+                        stopping: inst.stopping,
+                        order_id: inst.order_id.clone(),
+                        quantity: position.map(|p| p.quantity).unwrap_or_default(),
+                        buy_price: position.map(|p| p.buy_price).unwrap_or_default(),
+                        cost: position.map(|p| p.cost).unwrap_or_default(),
+                        pnl: inst.pnl,
+                    });
+                }
+                instances.sort_by(|a, b| a.id.cmp(&b.id));
+                let status = Status {
+                    principal: self.principal,
+                    profit: *self.state.profit.read().await,
+                    halted: self.risk.read().await.halted().map(str::to_string),
+                    instances,
+                    symbols: self.state.symbol_pnl(),
+                    rate_usage: self.exchange.rate_usage().await,
+                };
+                serde_json::to_value(status).map_err(|e| e.to_string())
+            }
+            Command::Orders => {
+                let mut orders = self
+                    .state
+                    .orders
+                    .read()
+                    .await
+                    .values()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                orders.sort_by_key(|o| o.id);
+                let archived_orders = self.state.archived_orders.read().await.clone();
+                Ok(json!({
+                    "orders": orders,
+                    "archived_orders": archived_orders,
+                }))
+            }
+            Command::Pause { inst_id } => self.pause(&inst_id, true).await,
+            Command::Resume { inst_id } => self.pause(&inst_id, false).await,
+            Command::ResumeTrading => {
+                let risk = {
+                    let mut risk = self.risk.write().await;
+                    risk.resume().ok_or("Trading is not halted")?;
+                    risk.state()
+                };
+                persist(&self.store, &Record::Risk(risk));
+                Ok(json!({ "halted": null }))
+            }
+            Command::Close { inst_id } => {
+                let instance = self
+                    .state
+                    .instances
+                    .get_mut(&inst_id)
+                    .ok_or_else(|| format!("Unknown instance {}", inst_id))?;
+                let closed = instance.close(&self.exchange, &self.state.orders).await;
+                persist_instance(&self.store, instance, &self.state.orders).await;
+                closed.map(|order_id| json!({ "order_id": order_id }))
+            }
         }
     }
 
+    // 暂停或恢复实例
+    async fn pause(&mut self, inst_id: &str, paused: bool) -> Reply {
+        let instance = self
+            .state
+            .instances
+            .get_mut(inst_id)
+            .ok_or_else(|| format!("Unknown instance {}", inst_id))?;
+        instance.paused = paused;
+        tracing::info!("Instance {} paused: {}", inst_id, paused);
+        persist_instance(&self.store, instance, &self.state.orders).await;
+        Ok(json!({ "inst_id": inst_id, "paused": paused }))
+    }
+
+    // 定期从交易所同步 REST 请求额度的用量，输出日志并更新指标
+    fn run_rate_monitor(&self) {
+        let exchange = self.exchange.clone();
+        tokio::spawn(async move {
+            let mut tick = time::interval(RATE_REPORT_INTERVAL);
+            loop {
+                tick.tick().await;
+                if let Err(e) = exchange.sync_rate_usage().await {
+                    tracing::error!("Sync rate usage failed, {:?}", e);
+                }
+                let usage = exchange.rate_usage().await;
+                if usage.is_empty() {
+                    return;
+                }
+                for u in &usage {
+                    METRICS
+                        .rate_used
+                        .with_label_values(&[u.name.as_str()])
+                        .set(u.used as f64);
+                    METRICS
+                        .rate_limit
+                        .with_label_values(&[u.name.as_str()])
+                        .set(u.limit as f64);
+                }
+                let usage = usage.iter().map(ToString::to_string).collect::<Vec<_>>();
+                tracing::info!("Rate usage, {}", usage.join(", "));
+            }
+        });
+    }
+
     // 订单监控
     fn run_order_monitor(&mut self) {
         // Update order
         let mut order_rx = self.order_channel.rx.take().unwrap();
         let orders = self.state.orders.clone();
+        let order_done_tx = self.order_done.tx.clone();
+        let store = self.store.clone();
+        let journal = self.journal.clone();
+        let routes = self.routes.clone();
         tokio::spawn({
-            let orders = orders;
             async move {
                 loop {
                     if let Some(order) = order_rx.recv().await {
                         tracing::info!("Handle OrderUpdate");
 
-                        if let Some(mut o) =
-                            orders.write().await.get_mut(&order.order_id.to_string())
-                        {
+                        let mut orders = orders.write().await;
+                        let order_id = order.order_id.to_string();
+                        // 下单响应丢失的订单，按客户端订单ID登记到所属实例
+                        let client_order_id = match &order.origin_client_id {
+                            Some(id) if !id.is_empty() => id.clone(),
+                            _ => order.client_order_id.clone().unwrap_or_default(),
+                        };
+                        if !orders.contains_key(&order_id) {
+                            let inst_id = routes
+                                .read()
+                                .await
+                                .iter()
+                                .find(|(prefix, _)| client_order_id.starts_with(prefix.as_str()))
+                                .map(|(_, inst_id)| inst_id.clone());
+                            if let Some(inst_id) = inst_id {
+                                tracing::info!("Route order {} to instance {}", order_id, inst_id);
+                                orders.insert(
+                                    order_id.clone(),
+                                    Order {
+                                        inst_id,
+                                        symbol: order.symbol.clone(),
+                                        id: order.order_id,
+                                        client_order_id,
+                                        strategies: Vec::new(),
+                                        side: order.side.clone(),
+                                        quality: order.qty,
+                                        price: order.price,
+                                        buy_price: order.price,
+                                        min_sell_price: min_sell_price(order.price),
+                                        status: OrderStatus::Committed,
+                                        filled: Fill::default(),
+                                        update_ts: 0,
+                                    },
+                                );
+                            }
+                        }
+
+                        if let Some(o) = orders.get_mut(&order_id) {
                             // compare time
-                            if order.event_time > o.update_ts {
-                                match order.current_order_status {
-                                    binance::rest_model::OrderStatus::New => {
-                                        o.status = OrderStatus::Accepted;
-                                    }
-                                    binance::rest_model::OrderStatus::Canceled => {
-                                        o.status = OrderStatus::Canceled;
+                            if order.event_time >= o.update_ts {
+                                o.update_ts = order.event_time;
+                                let status = OrderStatus::from(&order.current_order_status);
+                                if status != o.status {
+                                    let event = match status {
+                                        OrderStatus::Success => Some("filled"),
+                                        OrderStatus::Rejected => Some("rejected"),
+                                        _ => None,
+                                    };
+                                    if let Some(event) = event {
+                                        METRICS
+                                            .orders
+                                            .with_label_values(&[o.inst_id.as_str(), event])
+                                            .inc();
                                     }
-                                    binance::rest_model::OrderStatus::Trade => {
-                                        o.status = OrderStatus::Success;
+                                }
+                                o.status = status;
+                                if o.status == OrderStatus::Rejected {
+                                    tracing::error!(
+                                        "Order {} rejected, {}",
+                                        order_id,
+                                        order.order_reject_reason
+                                    );
+                                }
+                                // 累计成交以交易所推送的累计值为准，手续费按成交ID去重后逐笔累加
+                                o.filled.quantity = order.cumulative_filled_qty;
+                                if order.cumulative_filled_qty > 0. {
+                                    o.filled.avg_price = order
+                                        .cumulative_quote_asset_transacted_qty
+                                        / order.cumulative_filled_qty;
+                                }
+                                if order.execution_type == RestOrderStatus::Trade {
+                                    o.filled.add_commission(
+                                        order.trade_id,
+                                        order.commission,
+                                        order.commission_asset.as_deref(),
+                                    );
+                                }
+                                persist(&store, &Record::Order(o.clone()));
+                                match order.execution_type {
+                                    RestOrderStatus::New => record(&journal, &Entry::order(o)),
+                                    RestOrderStatus::Trade => {
+                                        record(&journal, &Entry::fill(o, &order))
                                     }
-                                    _ => {
-                                        tracing::info!("No need to handle order: {:?}", order);
+                                    _ => {}
+                                }
+                                if !o.status.is_open() {
+                                    if let Err(e) = order_done_tx.send(order.order_id.to_string()) {
+                                        tracing::error!("Send order done failed, {:?}", e);
                                     }
                                 }
                             }
@@ -344,19 +1590,293 @@ impl Engine {
             }
         });
 
+        // 挂单过期检查与已完成订单归档
         let orders = self.state.orders.clone();
+        let archived_orders = self.state.archived_orders.clone();
+        let stale_tx = self.stale.tx.clone();
+        let store = self.store.clone();
+        let timeouts = self.stale_timeouts.clone();
         tokio::spawn({
             let mut tick = time::interval(Duration::from_secs(1));
+            // 已通知过期的订单及通知时间，撤单失败时间隔重试
+            let mut notified: HashMap<OrderId, u64> = HashMap::new();
 
             async move {
                 loop {
                     tick.tick().await;
+                    let now = now();
                     let mut orders = orders.write().await;
-                    // for order in orders.keys() {
-                    //     orders.remove(order);
-                    // }
+
+                    let done = orders
+                        .values()
+                        .filter(|o| !o.status.is_open())
+                        .filter(|o| now.saturating_sub(o.update_ts) >= ARCHIVE_DELAY)
+                        .map(|o| o.id.to_string())
+                        .collect::<Vec<_>>();
+                    for order_id in done {
+                        if let Some(order) = orders.remove(&order_id) {
+                            persist(&store, &Record::Archive(order_id));
+                            store::archive(&mut *archived_orders.write().await, order);
+                        }
+                    }
+
+                    notified.retain(|order_id, _| orders.contains_key(order_id));
+                    let timeouts = timeouts.read().await;
+                    for (order_id, order) in orders.iter() {
+                        let timeout = timeouts.get(&order.inst_id).copied().unwrap_or_default();
+                        if timeout == 0
+                            || !matches!(
+                                order.status,
+                                OrderStatus::Committed
+                                    | OrderStatus::Accepted
+                                    | OrderStatus::PartiallyFilled
+                            )
+                            || now.saturating_sub(order.update_ts) < timeout
+                        {
+                            continue;
+                        }
+                        if let Some(ts) = notified.get(order_id) {
+                            if now.saturating_sub(*ts) < STALE_RETRY {
+                                continue;
+                            }
+                        }
+                        notified.insert(order_id.clone(), now);
+                        if stale_tx.send(order_id.clone()).is_err() {
+                            return;
+                        }
+                    }
                 }
             }
         });
     }
 }
+
+/// 按配置创建实例
+fn build_instance(
+    conf: &InstanceConfig,
+    capital: &Arc<RwLock<Capital>>,
+    optimal_price: &Arc<RwLock<HashMap<Symbol, Price>>>,
+    risk: &Arc<RwLock<Risk>>,
+    order_done: &UnboundedSender<OrderId>,
+) -> Instance {
+    let strategies = conf
+        .strategies
+        .iter()
+        .map(|strategy| (strategy.build(), strategy.weight()))
+        .collect();
+    Instance::new(
+        &conf.id,
+        &conf.symbol.to_uppercase(),
+        conf.mode.clone(),
+        conf.threshold,
+        conf.stop_loss,
+        capital.clone(),
+        strategies,
+    )
+    .with_strategy_ids(conf.strategies.iter().map(|s| s.id().to_string()).collect())
+    .with_quotes(optimal_price.clone(), conf.price_offset)
+    .with_stale_order(conf.stale_order.clone())
+    .with_risk(risk.clone())
+    .with_order_done(order_done.clone())
+}
+
+/// 校验实例ID：不能为空或重复，客户端订单ID前缀也不能相同，否则订单无法路由到所属实例
+fn check_instances(config: &Config) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    let mut prefixes = HashMap::new();
+    for inst_conf in &config.instances {
+        if inst_conf.id.is_empty() {
+            return Err(format!(
+                "Instance of {} has no id, run bq inject to add one",
+                inst_conf.symbol
+            ));
+        }
+        if !ids.insert(inst_conf.id.as_str()) {
+            return Err(format!("Duplicate instance id {}", inst_conf.id));
+        }
+        let prefix = client_order_prefix(&inst_conf.id);
+        if let Some(other) = prefixes.insert(prefix.clone(), inst_conf.id.as_str()) {
+            return Err(format!(
+                "Instances {} and {} share client order id prefix {}",
+                other, inst_conf.id, prefix
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// 实例所需的数据通道及对应的数据流
+fn subscriptions(conf: &InstanceConfig) -> Vec<(DataChannelIndex, String)> {
+    let symbol = conf.symbol.to_uppercase();
+    let mut subscriptions = conf
+        .strategies
+        .iter()
+        .map(|strategy| {
+            let interval = strategy.interval();
+            (
+                (symbol.clone(), Category::Kline(interval.clone())).data_index(),
+                kline_stream(&conf.symbol, &interval.to_string()),
+            )
+        })
+        .collect::<Vec<_>>();
+    subscriptions.push((
+        (symbol, Category::BookTicker).data_index(),
+        book_ticker_stream(&conf.symbol),
+    ));
+    subscriptions
+}
+
+/// 当前时间戳，单位毫秒
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+// 写入状态日志，失败只记录日志，不中断交易
+fn persist(store: &Option<Arc<Mutex<Store>>>, record: &Record) {
+    if let Some(store) = store {
+        if let Err(e) = store.lock().unwrap().append(record) {
+            tracing::error!("Persist state failed, {:?}", e);
+        }
+    }
+}
+
+// 追加一条交易日志
+fn record(journal: &Option<Arc<Mutex<Journal>>>, entry: &Entry) {
+    if let Some(journal) = journal {
+        if let Err(e) = journal.lock().unwrap().append(entry) {
+            tracing::error!("Record journal failed, {:?}", e);
+        }
+    }
+}
+
+// 写回订单的手续费折算与已实现盈亏
+async fn update_order(
+    store: &Option<Arc<Mutex<Store>>>,
+    orders: &RwLock<HashMap<OrderId, Order>>,
+    order: &Order,
+) {
+    if let Some(o) = orders.write().await.get_mut(&order.id.to_string()) {
+        o.filled.commission_quote = order.filled.commission_quote;
+        o.filled.pnl = order.filled.pnl;
+        persist(store, &Record::Order(o.clone()));
+    }
+}
+
+// 记录实例状态及其当前订单
+async fn persist_instance(
+    store: &Option<Arc<Mutex<Store>>>,
+    instance: &Instance,
+    orders: &RwLock<HashMap<OrderId, Order>>,
+) {
+    if store.is_none() {
+        return;
+    }
+    if let Some(order_id) = &instance.order_id {
+        if let Some(order) = orders.read().await.get(order_id) {
+            persist(store, &Record::Order(order.clone()));
+        }
+    }
+    persist(store, &Record::Instance(instance.snapshot().await));
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use binance::rest_model::{
+        Order as RestOrder, OrderSide, OrderStatus as RestOrderStatus, OrderType, TimeInForce,
+    };
+
+    use super::{check_instances, config::Config, Fill, Order, OrderStatus};
+
+    #[test]
+    fn test_check_instances() {
+        let config = |ids: &[&str]| -> Config {
+            let instances = ids
+                .iter()
+                .map(|id| {
+                    json!({
+                        "id": id,
+                        "symbol": "btcusdt",
+                        "mode": "or",
+                        "strategies": [],
+                        "stop_loss": 0.1,
+                        "principal": 1.0,
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::from_value(json!({ "principal": 10.0, "instances": instances })).unwrap()
+        };
+        assert!(check_instances(&config(&["btc-1", "btc-2"])).is_ok());
+        assert!(check_instances(&config(&["btc-1", ""])).is_err());
+        assert!(check_instances(&config(&["btc-1", "btc-1"])).is_err());
+        // 前缀只取字母与数字，不同的ID可能得到相同的前缀
+        assert!(check_instances(&config(&["btc-1", "btc_1"])).is_err());
+    }
+
+    #[test]
+    fn test_sync() {
+        let mut order = Order {
+            inst_id: "inst-1".to_string(),
+            symbol: "BTCUSDT".to_string(),
+            id: 1,
+            client_order_id: "bqinst1-1".to_string(),
+            strategies: Vec::new(),
+            side: OrderSide::Sell,
+            quality: 2.,
+            price: 10.,
+            buy_price: 9.,
+            min_sell_price: 9.02,
+            status: OrderStatus::PartiallyFilled,
+            filled: Fill::default(),
+            update_ts: 1,
+        };
+        order.filled.quantity = 1.;
+        order.filled.avg_price = 10.;
+        order.filled.add_commission(1, 0.01, Some("USDT"));
+        let rest = |status, executed_qty| RestOrder {
+            symbol: "BTCUSDT".to_string(),
+            order_id: 1,
+            order_list_id: -1,
+            client_order_id: "bqinst1-1".to_string(),
+            price: 10.,
+            orig_qty: 2.,
+            executed_qty,
+            cummulative_quote_qty: executed_qty * 10.,
+            status,
+            time_in_force: TimeInForce::GTC,
+            order_type: OrderType::Limit,
+            side: OrderSide::Sell,
+            stop_price: 0.,
+            iceberg_qty: 0.,
+            time: 0,
+            update_time: 2,
+            is_working: false,
+            orig_quote_order_qty: 0.,
+        };
+
+        assert!(!order.sync(&rest(RestOrderStatus::PartiallyFilled, 1.)));
+        assert_eq!(order.filled.commission, 0.01);
+        // 断线期间成交的剩余部分，手续费改按默认费率估算
+        assert!(order.sync(&rest(RestOrderStatus::Filled, 2.)));
+        assert_eq!(order.status, OrderStatus::Success);
+        assert_eq!(order.filled.quantity, 2.);
+        assert!(order.filled.commission_asset.is_empty());
+        assert!((order.commission_value() - 0.02).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_add_commission() {
+        let mut fill = Fill::default();
+        fill.add_commission(101, 0.001, Some("BNB"));
+        // 重连后重复推送的成交回报
+        fill.add_commission(101, 0.001, Some("BNB"));
+        fill.add_commission(102, 0.002, Some("BNB"));
+        assert!((fill.commission - 0.003).abs() < 1e-12);
+        assert_eq!(fill.last_trade_id, 102);
+        assert_eq!(fill.commission_asset, "BNB");
+    }
+}
@@ -0,0 +1,201 @@
+use std::{io, net::SocketAddr, sync::LazyLock};
+
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// 引擎运行指标，进程内全局共享，配置 `metrics_addr` 后以 Prometheus 文本格式导出。
+pub(crate) static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// 订单往返延迟的分桶，单位秒
+const LATENCY_BUCKETS: &[f64] = &[0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10.];
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    pub(crate) stream_events: IntCounterVec, // 各数据流收到的事件数
+    pub(crate) broadcast_lagged: IntCounterVec, // 接收方处理不及被丢弃的消息数
+    pub(crate) signals: IntCounterVec,       // 各策略产生的信号数
+    pub(crate) orders: IntCounterVec,        // 下单、成交、拒绝的订单数
+    pub(crate) order_latency: HistogramVec,  // 下单请求的往返延迟
+    pub(crate) realized_pnl: GaugeVec,       // 本次运行的已实现盈亏
+    pub(crate) unrealized_pnl: GaugeVec,     // 持仓按最新价格计算的浮动盈亏
+    pub(crate) ws_reconnects: IntCounterVec, // WebSocket 重连次数
+    pub(crate) rate_used: GaugeVec,          // 各限额窗口的请求权重或下单次数用量
+    pub(crate) rate_limit: GaugeVec,         // 各限额窗口的上限
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        let gauge = |name: &str, help: &str, labels: &[&str]| {
+            let gauge = GaugeVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(gauge.clone())).unwrap();
+            gauge
+        };
+
+        let stream_events = counter(
+            "bq_stream_events_total",
+            "Events received per stream",
+            &["stream"],
+        );
+        let broadcast_lagged = counter(
+            "bq_broadcast_lagged_total",
+            "Messages skipped by lagging broadcast receivers",
+            &["receiver"],
+        );
+        let signals = counter(
+            "bq_signals_total",
+            "Signals emitted per strategy and type",
+            &["inst_id", "strategy", "signal"],
+        );
+        let orders = counter(
+            "bq_orders_total",
+            "Orders placed, filled and rejected",
+            &["inst_id", "event"],
+        );
+        let realized_pnl = gauge("bq_realized_pnl", "Realized PnL since start", &["inst_id"]);
+        let unrealized_pnl = gauge(
+            "bq_unrealized_pnl",
+            "Unrealized PnL of the open position",
+            &["inst_id"],
+        );
+        let ws_reconnects = counter(
+            "bq_ws_reconnects_total",
+            "WebSocket reconnects",
+            &["reason"],
+        );
+        let rate_used = gauge(
+            "bq_rate_limit_used",
+            "Request weight or order count used in the current rate limit window",
+            &["window"],
+        );
+        let rate_limit = gauge(
+            "bq_rate_limit",
+            "Request weight or order count limit of the rate limit window",
+            &["window"],
+        );
+        let order_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "bq_order_latency_seconds",
+                "Round-trip latency of order placement requests",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["inst_id"],
+        )
+        .unwrap();
+        registry.register(Box::new(order_latency.clone())).unwrap();
+
+        Self {
+            registry,
+            stream_events,
+            broadcast_lagged,
+            signals,
+            orders,
+            order_latency,
+            realized_pnl,
+            unrealized_pnl,
+            ws_reconnects,
+            rate_used,
+            rate_limit,
+        }
+    }
+
+    /// 实例移除后清理其指标
+    pub(crate) fn remove_instance(&self, inst_id: &str) {
+        let _ = self.realized_pnl.remove_label_values(&[inst_id]);
+        let _ = self.unrealized_pnl.remove_label_values(&[inst_id]);
+    }
+
+    /// Prometheus 文本格式
+    pub(crate) fn encode(&self) -> String {
+        let mut buf = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buf) {
+            tracing::error!("Encode metrics failed, {:?}", e);
+        }
+        String::from_utf8(buf).unwrap_or_default()
+    }
+}
+
+/// 在 `addr` 上提供 `GET /metrics`，供 Prometheus 抓取，返回实际监听的地址。
+pub(crate) async fn serve(addr: &str) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    tracing::info!("Metrics server listening on {}", local_addr);
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::error!("Accept metrics connection failed, {:?}", e);
+                    continue;
+                }
+            };
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream).await {
+                    tracing::error!("Handle metrics connection failed, {:?}", e);
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+async fn handle(mut stream: TcpStream) -> io::Result<()> {
+    // 只需要请求行，抓取请求没有请求体
+    let mut buf = [0u8; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let (status, body) = match request.lines().next() {
+        Some(line) if line.starts_with("GET /metrics ") => ("200 OK", METRICS.encode()),
+        _ => ("404 Not Found", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{serve, METRICS};
+
+    #[tokio::test]
+    async fn test_metrics() {
+        METRICS
+            .orders
+            .with_label_values(&["test-metrics", "placed"])
+            .inc();
+        METRICS
+            .order_latency
+            .with_label_values(&["test-metrics"])
+            .observe(0.03);
+
+        let addr = serve("127.0.0.1:0").await.unwrap();
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(r#"bq_orders_total{event="placed",inst_id="test-metrics"} 1"#));
+        assert!(response
+            .contains(r#"bq_order_latency_seconds_bucket{inst_id="test-metrics",le="0.05"} 1"#));
+    }
+}
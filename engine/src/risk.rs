@@ -0,0 +1,311 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use binance::rest_model::OrderSide;
+use serde::{Deserialize, Serialize};
+
+use crate::{capital::Capital, config::RiskLimits, InstId, Symbol};
+
+/// 一天的毫秒数，按 UTC 日期重置当日亏损
+const DAY: u64 = 86_400_000;
+/// 下单频率的统计窗口，单位毫秒
+const MINUTE: u64 = 60_000;
+
+/// 订单未通过风控的原因
+#[derive(Debug, PartialEq)]
+pub enum RiskError {
+    /// 已停止交易
+    Halted(String),
+    /// 交易对的敞口超出上限
+    SymbolExposure {
+        symbol: Symbol,
+        exposure: f64,
+        limit: f64,
+    },
+    /// 总敞口超出上限
+    TotalExposure { exposure: f64, limit: f64 },
+    /// 下单过于频繁
+    OrderRate { limit: u32 },
+}
+
+impl fmt::Display for RiskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskError::Halted(reason) => write!(f, "trading halted, {}", reason),
+            RiskError::SymbolExposure {
+                symbol,
+                exposure,
+                limit,
+            } => write!(
+                f,
+                "{} exposure {} exceeds limit {}",
+                symbol, exposure, limit
+            ),
+            RiskError::TotalExposure { exposure, limit } => {
+                write!(f, "total exposure {} exceeds limit {}", exposure, limit)
+            }
+            RiskError::OrderRate { limit } => {
+                write!(f, "more than {} orders per minute", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RiskError {}
+
+/// 需要持久化的风控状态，重启后恢复，避免重启绕过当日亏损上限或解除停止交易
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RiskState {
+    pub(crate) day: u64, // 当日亏损所属的 UTC 日期，自纪元起的天数
+    pub(crate) daily_pnl: f64,
+    pub(crate) halted: Option<String>,
+}
+
+/// 全局风控：买单提交前检查敞口与下单频率，已实现亏损超出当日上限或权益回撤超出上限时停止交易。
+/// 卖单只减少已有持仓，止损、退出平仓与手动平仓在停止交易后仍要能离场，不做检查。
+///
+/// 敞口取各实例在资金分配中占用的资金，即买单与持仓的成本；权益按本金加已实现盈亏计算，
+/// 持仓的浮动亏损不计入，回撤只在卖出实现亏损后触发，持仓期间的下跌由止损控制。
+#[derive(Debug, Default)]
+pub(crate) struct Risk {
+    limits: RiskLimits,
+    symbols: HashMap<InstId, Symbol>, // 实例所属的交易对
+    equity: f64,
+    peak_equity: f64,
+    day: u64,
+    daily_pnl: f64,
+    orders: VecDeque<u64>, // 最近一分钟的下单时间
+    halted: Option<String>,
+}
+
+impl Risk {
+    pub(crate) fn new(limits: RiskLimits, equity: f64, symbols: HashMap<InstId, Symbol>) -> Self {
+        Self {
+            limits,
+            symbols,
+            equity,
+            peak_equity: equity,
+            ..Default::default()
+        }
+    }
+
+    /// 恢复重启前的累计收益与风控状态
+    pub(crate) fn restore(&mut self, profit: f64, state: RiskState) {
+        self.equity += profit;
+        self.peak_equity = self.peak_equity.max(self.equity);
+        self.day = state.day;
+        self.daily_pnl = state.daily_pnl;
+        if let Some(reason) = state.halted {
+            tracing::error!("Risk halted trading before restart, {}", reason);
+            self.halted = Some(reason);
+        }
+    }
+
+    /// 需要持久化的风控状态
+    pub(crate) fn state(&self) -> RiskState {
+        RiskState {
+            day: self.day,
+            daily_pnl: self.daily_pnl,
+            halted: self.halted.clone(),
+        }
+    }
+
+    /// 应用热加载的风控配置，本金的变化计入权益
+    pub(crate) fn reconfigure(
+        &mut self,
+        limits: RiskLimits,
+        principal_change: f64,
+        symbols: HashMap<InstId, Symbol>,
+    ) {
+        self.limits = limits;
+        self.symbols = symbols;
+        self.equity += principal_change;
+        self.peak_equity += principal_change;
+    }
+
+    /// 停止交易的原因
+    pub(crate) fn halted(&self) -> Option<&str> {
+        self.halted.as_deref()
+    }
+
+    /// 停止交易，之后买单都会被拒绝。
+    pub(crate) fn halt(&mut self, reason: String) {
+        if self.halted.is_none() {
+            tracing::error!("Risk halted trading, {}", reason);
+            self.halted = Some(reason);
+        }
+    }
+
+    /// 解除停止交易，返回原来的原因。当日亏损仍超出上限时，下一笔亏损会再次停止交易。
+    pub(crate) fn resume(&mut self) -> Option<String> {
+        let reason = self.halted.take();
+        if let Some(reason) = &reason {
+            tracing::info!("Risk resumed trading, halted by {}", reason);
+        }
+        reason
+    }
+
+    /// 检查即将提交的订单，`now` 为当前时间戳（毫秒）。买单的资金在提交前已占用，敞口已包含本单。
+    /// 现货卖单只能卖出已有持仓，直接通过，也不计入下单频率。
+    pub(crate) fn check(
+        &mut self,
+        inst_id: &str,
+        side: &OrderSide,
+        capital: &Capital,
+        now: u64,
+    ) -> Result<(), RiskError> {
+        if *side == OrderSide::Sell {
+            return Ok(());
+        }
+        if let Some(reason) = &self.halted {
+            return Err(RiskError::Halted(reason.clone()));
+        }
+
+        if let Some(symbol) = self.symbols.get(inst_id) {
+            let exposure = self
+                .symbols
+                .iter()
+                .filter(|(_, s)| *s == symbol)
+                .map(|(id, _)| capital.used(id))
+                .sum::<f64>();
+            let limit = self.limits.max_symbol_exposure;
+            if limit > 0. && exposure > limit {
+                return Err(RiskError::SymbolExposure {
+                    symbol: symbol.clone(),
+                    exposure,
+                    limit,
+                });
+            }
+        }
+        let exposure = capital.total_used();
+        let limit = self.limits.max_total_exposure;
+        if limit > 0. && exposure > limit {
+            return Err(RiskError::TotalExposure { exposure, limit });
+        }
+
+        while self
+            .orders
+            .front()
+            .is_some_and(|&ts| now.saturating_sub(ts) >= MINUTE)
+        {
+            self.orders.pop_front();
+        }
+        let limit = self.limits.max_orders_per_minute;
+        if limit > 0 && self.orders.len() >= limit as usize {
+            return Err(RiskError::OrderRate { limit });
+        }
+        self.orders.push_back(now);
+        Ok(())
+    }
+
+    /// 记录一笔已实现盈亏，超出当日亏损或回撤上限时停止交易。
+    pub(crate) fn record_pnl(&mut self, pnl: f64, now: u64) {
+        let day = now / DAY;
+        if day != self.day {
+            self.day = day;
+            self.daily_pnl = 0.;
+        }
+        self.daily_pnl += pnl;
+        self.equity += pnl;
+        self.peak_equity = self.peak_equity.max(self.equity);
+
+        let limit = self.limits.max_daily_loss;
+        if limit > 0. && -self.daily_pnl >= limit {
+            self.halt(format!(
+                "daily loss {} reached limit {}",
+                -self.daily_pnl, limit
+            ));
+        }
+        let limit = self.limits.max_drawdown;
+        if limit > 0. && self.peak_equity > 0. {
+            let drawdown = (self.peak_equity - self.equity) / self.peak_equity;
+            if drawdown >= limit {
+                self.halt(format!(
+                    "drawdown {:.4} from peak equity {} reached limit {}",
+                    drawdown, self.peak_equity, limit
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use binance::rest_model::OrderSide;
+
+    use super::{Risk, RiskError, DAY, MINUTE};
+    use crate::{capital::Capital, config::RiskLimits};
+
+    #[test]
+    fn test_risk() {
+        let limits = RiskLimits {
+            max_symbol_exposure: 50.,
+            max_total_exposure: 70.,
+            max_daily_loss: 20.,
+            max_drawdown: 0.1,
+            max_orders_per_minute: 2,
+        };
+        let symbols = HashMap::from([
+            ("a".to_string(), "BTCUSDT".to_string()),
+            ("b".to_string(), "ETHUSDT".to_string()),
+        ]);
+        let mut risk = Risk::new(limits.clone(), 100., symbols);
+        let mut capital = Capital::new(100.);
+        capital.reserve("a", 60.).unwrap();
+        capital.reserve("b", 40.).unwrap();
+
+        capital.lock("a", 60.).unwrap();
+        assert!(matches!(
+            risk.check("a", &OrderSide::Buy, &capital, 0),
+            Err(RiskError::SymbolExposure { .. })
+        ));
+        capital.release("a", 20.);
+        capital.lock("b", 40.).unwrap();
+        assert!(matches!(
+            risk.check("b", &OrderSide::Buy, &capital, 0),
+            Err(RiskError::TotalExposure { .. })
+        ));
+
+        capital.release("b", 40.);
+        risk.check("a", &OrderSide::Buy, &capital, 0).unwrap();
+        risk.check("a", &OrderSide::Buy, &capital, 1).unwrap();
+        assert_eq!(
+            risk.check("a", &OrderSide::Buy, &capital, 2),
+            Err(RiskError::OrderRate { limit: 2 })
+        );
+        // 卖单不计入下单频率
+        risk.check("a", &OrderSide::Sell, &capital, 2).unwrap();
+        risk.check("a", &OrderSide::Buy, &capital, MINUTE).unwrap();
+
+        risk.record_pnl(4., 0);
+        risk.record_pnl(-6., 0);
+        assert_eq!(risk.halted(), None);
+        risk.record_pnl(-8., 0);
+        assert!(risk.halted().unwrap().starts_with("drawdown"));
+        assert!(matches!(
+            risk.check("a", &OrderSide::Buy, &capital, MINUTE + 1),
+            Err(RiskError::Halted(_))
+        ));
+        // 停止交易后仍可卖出离场
+        risk.check("a", &OrderSide::Sell, &capital, MINUTE + 1)
+            .unwrap();
+
+        // 重启后恢复停止交易与当日亏损
+        let mut restored = Risk::new(limits.clone(), 100., HashMap::new());
+        restored.restore(-10., risk.state());
+        assert_eq!(restored.halted(), risk.halted());
+        assert!(restored.resume().is_some());
+        restored.record_pnl(-11., 0);
+        assert!(restored.halted().unwrap().starts_with("daily loss"));
+        // 次日重新计算当日亏损
+        let mut restored = Risk::new(limits, 100., HashMap::new());
+        restored.restore(-10., risk.state());
+        restored.record_pnl(-1., DAY);
+        assert_eq!(restored.state().day, 1);
+        assert_eq!(restored.state().daily_pnl, -1.);
+    }
+}
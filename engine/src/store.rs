@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{instance::InstanceSnapshot, risk::RiskState, InstId, Order, OrderId};
+
+/// 快照中保留的已归档订单数量，更早的订单只保留在交易日志中
+const MAX_ARCHIVED_ORDERS: usize = 1000;
+
+/// 日志中的一条状态变更
+#[derive(Serialize, Deserialize)]
+pub(crate) enum Record {
+    /// 新增或更新订单
+    Order(Order),
+    /// 订单归档
+    Archive(OrderId),
+    /// 实例状态
+    Instance(InstanceSnapshot),
+    /// 累计收益
+    Profit(f64),
+    /// 风控的当日亏损与停止交易
+    Risk(RiskState),
+}
+
+/// 回放日志得到的引擎状态
+#[derive(Default, Clone)]
+pub(crate) struct Snapshot {
+    pub(crate) orders: HashMap<OrderId, Order>,
+    pub(crate) archived_orders: Vec<Order>,
+    pub(crate) profit: f64,
+    pub(crate) instances: HashMap<InstId, InstanceSnapshot>,
+    pub(crate) risk: RiskState,
+}
+
+impl Snapshot {
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Order(order) => {
+                self.orders.insert(order.id.to_string(), order);
+            }
+            Record::Archive(order_id) => {
+                if let Some(order) = self.orders.remove(&order_id) {
+                    archive(&mut self.archived_orders, order);
+                }
+            }
+            Record::Instance(inst) => {
+                self.instances.insert(inst.id.clone(), inst);
+            }
+            Record::Profit(profit) => {
+                self.profit = profit;
+            }
+            Record::Risk(risk) => {
+                self.risk = risk;
+            }
+        }
+    }
+
+    fn into_records(self) -> impl Iterator<Item = Record> {
+        let archived = self.archived_orders.into_iter().flat_map(|order| {
+            let order_id = order.id.to_string();
+            [Record::Order(order), Record::Archive(order_id)]
+        });
+        archived
+            .chain(self.orders.into_values().map(Record::Order))
+            .chain(self.instances.into_values().map(Record::Instance))
+            .chain([Record::Profit(self.profit), Record::Risk(self.risk)])
+    }
+}
+
+/// 归档订单，超出数量上限时丢弃最早归档的订单
+pub(crate) fn archive(archived_orders: &mut Vec<Order>, order: Order) {
+    archived_orders.push(order);
+    let excess = archived_orders.len().saturating_sub(MAX_ARCHIVED_ORDERS);
+    archived_orders.drain(..excess);
+}
+
+/// 状态持久化：每次变更以一行 JSON 追加到日志并落盘，启动时回放恢复。
+pub(crate) struct Store {
+    path: PathBuf,
+    file: File,
+}
+
+impl Store {
+    /// 打开日志并回放，随后压缩为快照，避免日志无限增长。
+    pub(crate) fn open(path: impl AsRef<Path>) -> io::Result<(Self, Snapshot)> {
+        let path = path.as_ref().to_path_buf();
+        let mut snapshot = Snapshot::default();
+        if path.exists() {
+            for (no, line) in BufReader::new(File::open(&path)?).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(&line) {
+                    Ok(record) => snapshot.apply(record),
+                    // 崩溃时最后一行可能只写了一半
+                    Err(e) => tracing::error!("Skip broken record at line {}, {:?}", no + 1, e),
+                }
+            }
+        }
+
+        let file = Self::rewrite(&path, snapshot.clone().into_records())?;
+        Ok((Self { path, file }, snapshot))
+    }
+
+    /// 追加一条记录并同步到磁盘
+    pub(crate) fn append(&mut self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()
+    }
+
+    /// 用完整状态替换日志
+    pub(crate) fn compact(&mut self, snapshot: Snapshot) -> io::Result<()> {
+        self.file = Self::rewrite(&self.path, snapshot.into_records())?;
+        Ok(())
+    }
+
+    // 先写临时文件再改名，保证任意时刻磁盘上都是完整的日志
+    fn rewrite(path: &Path, records: impl Iterator<Item = Record>) -> io::Result<File> {
+        let tmp = path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            for record in records {
+                let mut line = serde_json::to_string(&record)?;
+                line.push('\n');
+                file.write_all(line.as_bytes())?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, path)?;
+        OpenOptions::new().append(true).open(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use binance::rest_model::OrderSide;
+
+    use super::{archive, Record, Store, MAX_ARCHIVED_ORDERS};
+    use crate::{risk::RiskState, Order, OrderStatus};
+
+    fn order(id: u64, status: OrderStatus) -> Order {
+        Order {
+            inst_id: "inst-1".to_string(),
+            symbol: "BTCUSDT".to_string(),
+            id,
+            client_order_id: format!("bqinst1-{}", id),
+            strategies: Vec::new(),
+            side: OrderSide::Buy,
+            quality: 1.,
+            price: 10.,
+            buy_price: 10.,
+            min_sell_price: 10.02,
+            status,
+            filled: Default::default(),
+            update_ts: 0,
+        }
+    }
+
+    #[test]
+    fn test_replay() {
+        let path = std::env::temp_dir().join(format!("bq-store-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let (mut store, snapshot) = Store::open(&path).unwrap();
+            assert!(snapshot.orders.is_empty());
+            store
+                .append(&Record::Order(order(1, OrderStatus::Committed)))
+                .unwrap();
+            store
+                .append(&Record::Order(order(1, OrderStatus::Success)))
+                .unwrap();
+            store.append(&Record::Archive("1".to_string())).unwrap();
+            store
+                .append(&Record::Order(order(2, OrderStatus::Accepted)))
+                .unwrap();
+            store.append(&Record::Profit(1.5)).unwrap();
+            store
+                .append(&Record::Risk(RiskState {
+                    day: 19_000,
+                    daily_pnl: -2.,
+                    halted: Some("daily loss".to_string()),
+                }))
+                .unwrap();
+            // 模拟崩溃时写了一半的记录
+            store.file.write_all(b"{\"Order\":{\"inst_").unwrap();
+        }
+
+        let (_, snapshot) = Store::open(&path).unwrap();
+        assert_eq!(snapshot.archived_orders.len(), 1);
+        assert_eq!(snapshot.archived_orders[0].status, OrderStatus::Success);
+        assert_eq!(snapshot.orders["2"].status, OrderStatus::Accepted);
+        assert_eq!(snapshot.profit, 1.5);
+        assert_eq!(snapshot.risk.daily_pnl, -2.);
+        assert_eq!(snapshot.risk.halted.as_deref(), Some("daily loss"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_archive() {
+        let mut archived_orders = Vec::new();
+        for id in 1..=MAX_ARCHIVED_ORDERS as u64 + 1 {
+            archive(&mut archived_orders, order(id, OrderStatus::Success));
+        }
+        assert_eq!(archived_orders.len(), MAX_ARCHIVED_ORDERS);
+        assert_eq!(archived_orders[0].id, 2);
+    }
+}
@@ -2,11 +2,7 @@ use criterion::{criterion_group, criterion_main, Criterion};
 use strategies::atr::{calculate_atr_by_fold, calculate_atr_by_for, calculate_atr_by_ndarray};
 
 fn build_prices() -> Vec<(f64, f64, f64)> {
-    let mut a = vec![];
-    for i in 0..60 * 60 * 24 * 14 {
-        a.push((50.0, 47.0, 50.0));
-    }
-    a
+    vec![(50.0, 47.0, 50.0); 60 * 60 * 24 * 14]
 }
 
 fn bench_calculate_atr(c: &mut Criterion) {
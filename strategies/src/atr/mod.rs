@@ -1,17 +1,14 @@
 use std::ops::Index;
 
-use binance::api::*;
-use futures::Future;
 use ndarray::{azip, s, Array1};
-use rayon::prelude::*;
 
-use crate::{Data, KlineInterval, Signal, Strategy};
-
-pub mod backtest;
+use crate::{Data, Evaluation, KlineInterval, Signal, Strategy};
 
 pub struct AverageTrueRange {
     period: u64,
     hlc_prices: Vec<(f64, f64, f64)>,
+    last_start_time: Option<i64>, // 最近一根K线的开盘时间
+    evaluation: Evaluation,
 
     pub(crate) interval: KlineInterval,
 }
@@ -21,13 +18,32 @@ impl AverageTrueRange {
         Self {
             period,
             hlc_prices: vec![],
+            last_start_time: None,
+            evaluation: Evaluation::default(),
             interval,
         }
     }
+
+    pub fn with_evaluation(mut self, evaluation: Evaluation) -> Self {
+        self.evaluation = evaluation;
+        self
+    }
+
+    /// 原地更新参数并保留已有数据，计算周期或K线周期不同时无法更新，返回 false。
+    pub fn update(&mut self, other: &Self) -> bool {
+        if self.period != other.period || self.interval != other.interval {
+            return false;
+        }
+        self.evaluation = other.evaluation;
+        true
+    }
+
     pub fn new_with_init_data() -> Self {
         Self {
             period: 14,
             hlc_prices: vec![],
+            last_start_time: None,
+            evaluation: Evaluation::default(),
             interval: KlineInterval::Day1,
         }
     }
@@ -35,14 +51,21 @@ impl AverageTrueRange {
 
 impl Strategy for AverageTrueRange {
     fn signal(&mut self, data: Data) -> Signal {
-        let Data::Kline(data) = data else{
+        let Data::Kline(data) = data else {
             return Signal::Nothing;
         };
         // perf: 一次性合并成三元组 避免不必要的zip
-        self.hlc_prices
-            .push((data.kline.high, data.kline.low, data.kline.close));
+        let hlc = (data.kline.high, data.kline.low, data.kline.close);
+        if !self.evaluation.update(
+            &mut self.hlc_prices,
+            &mut self.last_start_time,
+            &data.kline,
+            hlc,
+        ) {
+            return Signal::Nothing;
+        }
         if self.hlc_prices.len() >= (self.period + 1) as usize {
-            let atr = calculate_atr(&self.hlc_prices, self.period);
+            let _atr = calculate_atr(&self.hlc_prices, self.period);
 
             // 只保存最近2*period的数据
             if self.hlc_prices.len() > (self.period * 2) as usize {
@@ -57,13 +80,21 @@ impl Strategy for AverageTrueRange {
 
         Signal::Nothing
     }
+
+    fn evaluation(&self) -> Evaluation {
+        self.evaluation
+    }
+
+    fn warmup_bars(&self) -> usize {
+        (self.period + 1) as usize
+    }
 }
 
 pub fn calculate_atr(hlc_prices: &[(f64, f64, f64)], atr_period: u64) -> f64 {
     calculate_atr_by_fold(hlc_prices, atr_period)
 }
 
-pub fn calculate_atr_by_for(hlc_prices: &[(f64, f64, f64)], atr_period: u64) -> f64 {
+pub fn calculate_atr_by_for(hlc_prices: &[(f64, f64, f64)], _atr_period: u64) -> f64 {
     let mut tr_sum = 0.0;
     let mut prev_close = hlc_prices[0].2;
 
@@ -73,12 +104,10 @@ pub fn calculate_atr_by_for(hlc_prices: &[(f64, f64, f64)], atr_period: u64) ->
         prev_close = close;
     }
 
-    let atr = tr_sum / (hlc_prices.len() - 1) as f64;
-
-    atr
+    tr_sum / (hlc_prices.len() - 1) as f64
 }
 
-pub fn calculate_atr_by_fold(hlc_prices: &[(f64, f64, f64)], atr_period: u64) -> f64 {
+pub fn calculate_atr_by_fold(hlc_prices: &[(f64, f64, f64)], _atr_period: u64) -> f64 {
     // perf 不使用库，避免拷贝
     let tr_sum = hlc_prices
         .windows(2)
@@ -93,7 +122,7 @@ pub fn calculate_atr_by_fold(hlc_prices: &[(f64, f64, f64)], atr_period: u64) ->
     tr_sum / (hlc_prices.len() - 1) as f64
 }
 
-pub fn calculate_atr_by_ndarray(hlc_prices: &[(f64, f64, f64)], atr_period: usize) -> f64 {
+pub fn calculate_atr_by_ndarray(hlc_prices: &[(f64, f64, f64)], _atr_period: usize) -> f64 {
     let atr_period = hlc_prices.len() - 1;
 
     let (high, (low, close)): (Vec<_>, (Vec<_>, Vec<_>)) = hlc_prices
@@ -105,7 +134,7 @@ pub fn calculate_atr_by_ndarray(hlc_prices: &[(f64, f64, f64)], atr_period: usiz
     let low = Array1::from(low);
     let close = Array1::from(close);
     let mut true_ranges = Array1::zeros(high.len() - 1);
-    azip!((high in high.slice(s![1..]), low in low.slice(s![1..]), pc in close.slice(s![..-1]), ln in low.slice(s![1..]), mut tr in &mut true_ranges) {
+    azip!((high in high.slice(s![1..]), low in low.slice(s![1..]), pc in close.slice(s![..-1]), ln in low.slice(s![1..]), tr in &mut true_ranges) {
         *tr = (high - low).max(high - pc).max(pc - ln);
     });
 
@@ -119,13 +148,11 @@ pub fn calculate_atr_by_ndarray(hlc_prices: &[(f64, f64, f64)], atr_period: usiz
         previous_tr = *val.index(0);
     }
 
-    let atr = atr_sum / atr_period as f64;
-    atr
+    atr_sum / atr_period as f64
 }
 
+#[cfg(test)]
 mod tests {
-    use std::fmt::Display;
-
     use ta::{indicators::AverageTrueRange, Close, High, Low, Next};
 
     use crate::atr::{calculate_atr_by_fold, calculate_atr_by_for, calculate_atr_by_ndarray};
@@ -157,8 +184,8 @@ mod tests {
         let close = vec![46.0, 47.0, 48.0, 49.0, 50.0, 51.0];
         let prices = high
             .into_iter()
-            .zip(low.into_iter())
-            .zip(close.into_iter())
+            .zip(low)
+            .zip(close)
             .map(|e| (e.0 .0, e.0 .1, e.1))
             .collect::<Vec<_>>();
 
@@ -166,9 +193,9 @@ mod tests {
         let mut res = 0.0;
         for (h, l, c) in prices.iter() {
             res = atr.next(&Data {
-                high: h.clone(),
-                low: l.clone(),
-                close: c.clone(),
+                high: *h,
+                low: *l,
+                close: *c,
             });
         }
 
@@ -0,0 +1,204 @@
+use std::{fs, io, path::Path};
+
+use binance::ws_model::{Kline, KlineEvent};
+
+use crate::{Data, DataCategory, KlineInterval, Signal, Strategies, Strategy};
+
+/// 持仓状态，与引擎实例的 WaitBuy/WaitSell 周期一致。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    WaitBuy,
+    WaitSell,
+}
+
+/// 回测结果
+#[derive(Debug)]
+pub struct Report {
+    pub principal: f64,
+    pub equity: f64,
+    pub trades: usize,
+}
+
+impl Report {
+    /// 收益率
+    pub fn profit_rate(&self) -> f64 {
+        (self.equity - self.principal) / self.principal
+    }
+}
+
+/// 用历史K线驱动策略，在收盘价按全部资金买入、全部持仓卖出。
+pub struct Backtest {
+    strategies: Vec<Strategies>,
+    signals: Vec<Signal>, // 每个策略最近一次的信号
+    state: State,
+
+    principal: f64,
+    cash: f64,
+    quantity: f64,
+    fee_rate: f64,
+    trades: usize,
+}
+
+impl Backtest {
+    pub fn new(strategies: Vec<Strategies>, principal: f64, fee_rate: f64) -> Self {
+        Self {
+            signals: vec![Signal::Nothing; strategies.len()],
+            strategies,
+            state: State::WaitBuy,
+            principal,
+            cash: principal,
+            quantity: 0.,
+            fee_rate,
+            trades: 0,
+        }
+    }
+
+    /// `klines` 需按收盘时间排序，`decide` 将各策略的信号合并为一个交易决策。
+    pub fn run<F>(&mut self, klines: &[KlineEvent], mut decide: F) -> Report
+    where
+        F: FnMut(&[Signal]) -> Signal,
+    {
+        let mut last_price = 0.;
+        for kline in klines {
+            let category = kline.data_category();
+            for (strategy, signal) in self.strategies.iter_mut().zip(self.signals.iter_mut()) {
+                if strategy.data_category() == category {
+                    *signal = strategy.signal(Data::Kline(kline.clone()));
+                }
+            }
+
+            last_price = kline.kline.close;
+            match (self.state, decide(&self.signals)) {
+                (State::WaitBuy, Signal::Buy) => {
+                    self.quantity = self.cash * (1. - self.fee_rate) / last_price;
+                    self.cash = 0.;
+                    self.state = State::WaitSell;
+                    self.trades += 1;
+                }
+                (State::WaitSell, Signal::Sell) => {
+                    self.cash = self.quantity * last_price * (1. - self.fee_rate);
+                    self.quantity = 0.;
+                    self.state = State::WaitBuy;
+                    self.trades += 1;
+                }
+                _ => {}
+            }
+        }
+
+        Report {
+            principal: self.principal,
+            equity: self.cash + self.quantity * last_price,
+            trades: self.trades,
+        }
+    }
+}
+
+/// 读取目录下所有 `{symbol}-{interval}*.csv` 文件，兼容币安历史数据的文件命名。
+pub fn load_dir(
+    dir: impl AsRef<Path>,
+    symbol: &str,
+    interval: &KlineInterval,
+) -> io::Result<Vec<KlineEvent>> {
+    let prefix = format!("{}-{}", symbol, interval);
+    let mut paths = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(".csv"))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut klines = Vec::new();
+    for path in paths {
+        klines.append(&mut load_csv(path, symbol, interval)?);
+    }
+    Ok(klines)
+}
+
+/// 读取币安格式的K线 CSV：open_time,open,high,low,close,volume,close_time,...
+///
+/// 表头或无法解析的行会被跳过。
+pub fn load_csv(
+    path: impl AsRef<Path>,
+    symbol: &str,
+    interval: &KlineInterval,
+) -> io::Result<Vec<KlineEvent>> {
+    let content = fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .filter_map(|line| parse_line(line, symbol, interval))
+        .collect())
+}
+
+fn parse_line(line: &str, symbol: &str, interval: &KlineInterval) -> Option<KlineEvent> {
+    let mut fields = line.split(',').map(str::trim);
+    let start_time = fields.next()?.parse::<i64>().ok()?;
+    let open = fields.next()?.parse::<f64>().ok()?;
+    let high = fields.next()?.parse::<f64>().ok()?;
+    let low = fields.next()?.parse::<f64>().ok()?;
+    let close = fields.next()?.parse::<f64>().ok()?;
+    let volume = fields.next()?.parse::<f64>().ok()?;
+    let end_time = fields.next()?.parse::<i64>().ok()?;
+
+    Some(KlineEvent {
+        event_time: end_time as u64,
+        symbol: symbol.to_string(),
+        kline: Kline {
+            start_time,
+            end_time,
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            first_trade_id: 0,
+            last_trade_id: 0,
+            open,
+            close,
+            high,
+            low,
+            volume,
+            number_of_trades: 0,
+            is_final_bar: true,
+            quote_volume: 0.,
+            active_buy_volume: 0.,
+            active_volume_buy_quote: 0.,
+            ignore_me: String::new(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_line, Backtest};
+    use crate::{rsi::RelativeStrengthIndex, KlineInterval, Strategies};
+
+    #[test]
+    fn test_run() {
+        let interval = KlineInterval::Hour1;
+        let klines = [10., 9., 8., 7., 8., 9., 10., 11.]
+            .iter()
+            .enumerate()
+            .filter_map(|(i, close)| {
+                let line = format!(
+                    "{},{},{},{},{},0,{}",
+                    i * 10,
+                    close,
+                    close,
+                    close,
+                    close,
+                    i * 10 + 9
+                );
+                parse_line(&line, "BTCUSDT", &interval)
+            })
+            .collect::<Vec<_>>();
+        assert!(parse_line("open_time,open,high,low,close", "BTCUSDT", &interval).is_none());
+
+        let rsi = RelativeStrengthIndex::new(2, interval, 30., 70.);
+        let mut backtest = Backtest::new(vec![Strategies::RelativeStrengthIndex(rsi)], 100., 0.);
+        let report = backtest.run(&klines, |signals| signals[0]);
+
+        // 8 买入，10 卖出
+        assert_eq!(report.trades, 2);
+        assert!((report.equity - 125.).abs() < 1e-9);
+    }
+}
@@ -1,9 +1,13 @@
-use std::hash::Hash;
+use std::{
+    fmt,
+    fmt::{Display, Formatter},
+    hash::Hash,
+};
 
 use binance::ws_model::{BookTickerEvent, Kline, KlineEvent};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Signal {
     Buy,
     Sell,
@@ -12,6 +16,67 @@ pub enum Signal {
 
 pub trait Strategy {
     fn signal(&mut self, data: Data) -> Signal;
+
+    /// K线上的计算时机
+    fn evaluation(&self) -> Evaluation {
+        Evaluation::Close
+    }
+
+    /// 产生信号前需要的已收盘K线数量，启动时与数据流断线重连后用历史K线预热。
+    fn warmup_bars(&self) -> usize {
+        0
+    }
+}
+
+/// 策略在K线上的计算时机
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Evaluation {
+    /// 只在K线收盘时计算
+    #[default]
+    Close,
+    /// 每次K线更新都计算，未收盘的K线原地更新
+    Intrabar,
+}
+
+impl Evaluation {
+    /// 按计算时机把K线写入缓冲：收盘模式只追加已收盘的K线，盘中模式原地更新未收盘的K线。
+    /// 已写入的K线再次到达（断线后用历史K线补齐）时跳过；与上一根K线之间有缺失时丢弃旧数据重新积累。
+    /// 返回 false 表示本次更新无需计算。
+    pub(crate) fn update<T>(
+        &self,
+        bars: &mut Vec<T>,
+        last_start_time: &mut Option<i64>,
+        kline: &Kline,
+        bar: T,
+    ) -> bool {
+        if *self == Evaluation::Close && !kline.is_final_bar {
+            return false;
+        }
+        if let Some(last) = *last_start_time {
+            let replayed = match self {
+                Evaluation::Close => kline.start_time <= last,
+                Evaluation::Intrabar => kline.start_time < last,
+            };
+            if replayed {
+                return false;
+            }
+            // 一根K线的时长，下一根K线应紧接上一根开始
+            let interval = kline.end_time - kline.start_time + 1;
+            if kline.start_time > last + interval {
+                bars.clear();
+            }
+        }
+        match self {
+            Evaluation::Close => bars.push(bar),
+            Evaluation::Intrabar => match bars.last_mut() {
+                Some(last) if *last_start_time == Some(kline.start_time) => *last = bar,
+                _ => bars.push(bar),
+            },
+        }
+        *last_start_time = Some(kline.start_time);
+        true
+    }
 }
 
 /// 数据唯一性确定
@@ -29,6 +94,27 @@ pub enum Strategies {
     AverageTrueRange(atr::AverageTrueRange),
 }
 
+impl Strategies {
+    /// 策略名称，用于日志与指标
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategies::RelativeStrengthIndex(_) => "rsi",
+            Strategies::AverageTrueRange(_) => "atr",
+        }
+    }
+
+    /// 用新配置的策略原地更新参数并保留已有数据，类型、计算周期或K线周期不同时返回 false。
+    pub fn update(&mut self, other: &Strategies) -> bool {
+        match (self, other) {
+            (Strategies::RelativeStrengthIndex(r), Strategies::RelativeStrengthIndex(o)) => {
+                r.update(o)
+            }
+            (Strategies::AverageTrueRange(a), Strategies::AverageTrueRange(o)) => a.update(o),
+            _ => false,
+        }
+    }
+}
+
 impl Strategy for Strategies {
     fn signal(&mut self, data: Data) -> Signal {
         match self {
@@ -36,6 +122,20 @@ impl Strategy for Strategies {
             Strategies::AverageTrueRange(a) => a.signal(data),
         }
     }
+
+    fn evaluation(&self) -> Evaluation {
+        match self {
+            Strategies::RelativeStrengthIndex(r) => r.evaluation(),
+            Strategies::AverageTrueRange(a) => a.evaluation(),
+        }
+    }
+
+    fn warmup_bars(&self) -> usize {
+        match self {
+            Strategies::RelativeStrengthIndex(r) => r.warmup_bars(),
+            Strategies::AverageTrueRange(a) => a.warmup_bars(),
+        }
+    }
 }
 
 impl DataCategory for Strategies {
@@ -91,14 +191,14 @@ pub enum KlineInterval {
     Day1,
 }
 
-impl KlineInterval {
-    pub fn to_string(&self) -> String {
+impl Display for KlineInterval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            KlineInterval::Hour1 => "1h".to_string(),
-            KlineInterval::Hour2 => "2h".to_string(),
-            KlineInterval::Hour4 => "4h".to_string(),
-            KlineInterval::Hour6 => "6h".to_string(),
-            KlineInterval::Day1 => "1d".to_string(),
+            KlineInterval::Hour1 => write!(f, "1h"),
+            KlineInterval::Hour2 => write!(f, "2h"),
+            KlineInterval::Hour4 => write!(f, "4h"),
+            KlineInterval::Hour6 => write!(f, "6h"),
+            KlineInterval::Day1 => write!(f, "1d"),
         }
     }
 }
@@ -180,6 +280,7 @@ impl DataIndex<String> for BookTickerEvent {
 }
 
 pub mod atr;
+pub mod backtest;
 pub mod rsi;
 // pub mod grid;
 // pub mod sma;
@@ -189,7 +290,89 @@ pub mod rsi;
 
 #[cfg(test)]
 mod test {
-    use crate::Category;
+    use binance::ws_model::Kline;
+
+    use crate::Evaluation;
+
+    fn kline(start_time: i64, close: f64, is_final_bar: bool) -> Kline {
+        Kline {
+            start_time,
+            end_time: start_time + 59_999,
+            symbol: "BTCUSDT".to_string(),
+            interval: "1m".to_string(),
+            first_trade_id: 0,
+            last_trade_id: 0,
+            open: close,
+            close,
+            high: close,
+            low: close,
+            volume: 0.,
+            number_of_trades: 0,
+            is_final_bar,
+            quote_volume: 0.,
+            active_buy_volume: 0.,
+            active_volume_buy_quote: 0.,
+            ignore_me: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_evaluation() {
+        let updates = [
+            kline(0, 1., false),
+            kline(0, 2., true),
+            kline(60_000, 3., false),
+            kline(60_000, 4., false),
+        ];
+
+        let (mut bars, mut last_start_time) = (vec![], None);
+        let evaluated: Vec<bool> = updates
+            .iter()
+            .map(|k| Evaluation::Close.update(&mut bars, &mut last_start_time, k, k.close))
+            .collect();
+        assert_eq!(evaluated, [false, true, false, false]);
+        assert_eq!(bars, [2.]);
+
+        let (mut bars, mut last_start_time) = (vec![], None);
+        for k in &updates {
+            assert!(Evaluation::Intrabar.update(&mut bars, &mut last_start_time, k, k.close));
+        }
+        assert_eq!(bars, [2., 4.]);
+    }
+
+    #[test]
+    fn test_evaluation_gap() {
+        // 断线重连后补齐的K线与已有数据重叠，重叠部分跳过
+        let (mut bars, mut last_start_time) = (vec![], None);
+        for k in [
+            kline(0, 1., true),
+            kline(60_000, 2., true),
+            kline(60_000, 2., true),
+            kline(120_000, 3., true),
+        ] {
+            Evaluation::Close.update(&mut bars, &mut last_start_time, &k, k.close);
+        }
+        assert_eq!(bars, [1., 2., 3.]);
+
+        // 盘中模式下断线前未收盘的K线由补齐的收盘K线替换
+        let (mut bars, mut last_start_time) = (vec![], None);
+        for k in [
+            kline(0, 1., true),
+            kline(60_000, 2., false),
+            kline(0, 1., true),
+            kline(60_000, 3., true),
+        ] {
+            Evaluation::Intrabar.update(&mut bars, &mut last_start_time, &k, k.close);
+        }
+        assert_eq!(bars, [1., 3.]);
+
+        // 中间有缺失的K线，旧数据不再连续，丢弃后重新积累
+        let (mut bars, mut last_start_time) = (vec![], None);
+        for k in [kline(0, 1., true), kline(180_000, 4., true)] {
+            Evaluation::Close.update(&mut bars, &mut last_start_time, &k, k.close);
+        }
+        assert_eq!(bars, [4.]);
+    }
 
     #[test]
     fn test_it() {
@@ -1,14 +1,12 @@
-use binance::api::*;
 use ndarray::{s, Array1};
 use rayon::prelude::*;
-use ta::Next;
 
-use crate::{Data, KlineInterval, Signal, Strategy};
-
-pub mod backtest;
+use crate::{Data, Evaluation, KlineInterval, Signal, Strategy};
 
 pub struct RelativeStrengthIndex {
     close_prices: Vec<f64>,
+    last_start_time: Option<i64>, // 最近一根K线的开盘时间
+    evaluation: Evaluation,
 
     period: u64,
     buy_threshold: f64, // default
@@ -26,18 +24,38 @@ impl RelativeStrengthIndex {
     ) -> Self {
         Self {
             close_prices: vec![],
+            last_start_time: None,
+            evaluation: Evaluation::default(),
             period,
             interval,
             buy_threshold,
             sell_threshold,
         }
     }
+
+    pub fn with_evaluation(mut self, evaluation: Evaluation) -> Self {
+        self.evaluation = evaluation;
+        self
+    }
+
+    /// 原地更新参数并保留已有数据，计算周期或K线周期不同时无法更新，返回 false。
+    pub fn update(&mut self, other: &Self) -> bool {
+        if self.period != other.period || self.interval != other.interval {
+            return false;
+        }
+        self.buy_threshold = other.buy_threshold;
+        self.sell_threshold = other.sell_threshold;
+        self.evaluation = other.evaluation;
+        true
+    }
 }
 
 impl Default for RelativeStrengthIndex {
     fn default() -> Self {
         Self {
             close_prices: vec![],
+            last_start_time: None,
+            evaluation: Evaluation::default(),
             period: 14,
             buy_threshold: 30.,
             sell_threshold: 70.,
@@ -48,11 +66,18 @@ impl Default for RelativeStrengthIndex {
 
 impl Strategy for RelativeStrengthIndex {
     fn signal(&mut self, data: Data) -> Signal {
-        let Data::Kline(data) = data else{
+        let Data::Kline(data) = data else {
             return Signal::Nothing;
         };
         let close_price: f64 = data.kline.close;
-        self.close_prices.push(close_price);
+        if !self.evaluation.update(
+            &mut self.close_prices,
+            &mut self.last_start_time,
+            &data.kline,
+            close_price,
+        ) {
+            return Signal::Nothing;
+        }
 
         if self.close_prices.len() >= (self.period + 1) as usize {
             let rsi = calculate_rsi(&self.close_prices, self.period as usize);
@@ -73,15 +98,23 @@ impl Strategy for RelativeStrengthIndex {
 
         Signal::Nothing
     }
+
+    fn evaluation(&self) -> Evaluation {
+        self.evaluation
+    }
+
+    fn warmup_bars(&self) -> usize {
+        (self.period + 1) as usize
+    }
 }
 
 pub fn calculate_rsi(close_prices: &[f64], period: usize) -> f64 {
     calculate_rsi_by_rayon(close_prices, period)
 }
 
-pub fn calculate_rsi_by_for(prices: &[f64], period: usize) -> f64 {
+pub fn calculate_rsi_by_for(prices: &[f64], _period: usize) -> f64 {
     let period = prices.len() - 1;
-    let (prev, gain_sum, loss_sum) =
+    let (_prev, gain_sum, loss_sum) =
         prices
             .iter()
             .fold((&0., 0., 0.), |(prev, gain_sum, loss_sum), cur| {
@@ -97,17 +130,17 @@ pub fn calculate_rsi_by_for(prices: &[f64], period: usize) -> f64 {
                 }
             });
 
-    let mut avg_gain = gain_sum / period as f64;
-    let mut avg_loss = loss_sum / period as f64;
+    let avg_gain = gain_sum / period as f64;
+    let avg_loss = loss_sum / period as f64;
 
     (avg_gain / (avg_gain + avg_loss)) * 100.
 }
 
-pub fn calculate_rsi_by_ndarray(prices: &[f64], period: usize) -> f64 {
+pub fn calculate_rsi_by_ndarray(prices: &[f64], _period: usize) -> f64 {
     let period = prices.len() - 1;
     let prices = Array1::from_vec(prices.to_vec());
 
-    let gain_loss = &prices.slice(s![..-1]) - &prices.slice(s![1..]);
+    let gain_loss = &prices.slice(s![1..]) - &prices.slice(s![..-1]);
     let (gain_sum, loss_sum) =
         gain_loss
             .iter()
@@ -125,7 +158,7 @@ pub fn calculate_rsi_by_ndarray(prices: &[f64], period: usize) -> f64 {
     (avg_gain / (avg_gain + avg_loss)) * 100.
 }
 
-pub fn calculate_rsi_by_rayon(prices: &[f64], period: usize) -> f64 {
+pub fn calculate_rsi_by_rayon(prices: &[f64], _period: usize) -> f64 {
     let period = prices.len() - 1;
     let (gain_sum, loss_sum): (f64, f64) = prices
         .par_windows(2)
@@ -154,7 +187,7 @@ pub fn calculate_rsi_by_rayon(prices: &[f64], period: usize) -> f64 {
     (avg_gain / (avg_gain + avg_loss)) * 100.
 }
 
-pub fn calculate_rsi_by_rayon_and_ndarray(prices: &[f64], period: usize) -> f64 {
+pub fn calculate_rsi_by_rayon_and_ndarray(prices: &[f64], _period: usize) -> f64 {
     let period = prices.len() - 1;
     let prices = Array1::from_vec(prices.to_vec());
     let changes: Array1<f64> = &prices.slice(s![1..]) - &prices.slice(s![..-1]);
@@ -181,8 +214,8 @@ pub fn calculate_rsi_by_rayon_and_ndarray(prices: &[f64], period: usize) -> f64
     (avg_gain / (avg_gain + avg_loss)) * 100.
 }
 
+#[cfg(test)]
 mod tests {
-    use ndarray::array;
     use ta::{indicators::RelativeStrengthIndex, Next};
 
     use super::calculate_rsi_by_ndarray;
@@ -192,10 +225,6 @@ mod tests {
 
     #[test]
     fn test_it() {
-        let prices = array![
-            46.125, 47.125, 46.4375, 46.9375, 44.9375, 44.25, 44.625, 45.75, 47.8125, 47.5625,
-            47.0, 44.5625, 46.3125, 47.6875, 46.6875, 45.6875, 43.0625
-        ];
         let prices = vec![
             46.125, 47.125, 46.4375, 46.9375, 44.9375, 44.25, 44.625, 45.75, 47.8125, 47.5625,
             47.0, 44.5625, 46.3125, 47.6875, 46.6875, 45.6875, 43.0625,